@@ -0,0 +1,41 @@
+/* C API of afmt, the Salesforce Apex formatter. Generated by cbindgen, do not edit. */
+
+#ifndef AFMT_H
+#define AFMT_H
+
+#include <stddef.h>
+
+// The code was formatted.
+#define AFMT_OK 0
+
+// The code could not be parsed or formatted.
+#define AFMT_FORMAT_ERROR 2
+
+// A NULL pointer, source code or config that is not UTF-8, or an invalid config.
+#define AFMT_INVALID_ARGUMENT 3
+
+// Formats the `len` bytes of UTF-8 Apex code at `src`.
+//
+// `config_toml` is the content of an `.afmt.toml` file as a NUL-terminated string, or NULL for
+// the defaults. On success, `*out` receives the NUL-terminated formatted code, to be released
+// with `afmt_free`, and `AFMT_OK` is returned. Otherwise `*out` is set to NULL and the error
+// message is available from `afmt_last_error`.
+//
+// # Safety
+//
+// `src` must point to `len` readable bytes, `config_toml` must be NULL or a NUL-terminated
+// string, and `out` must be a valid pointer to write to.
+int afmt_format(const char *src, size_t len, const char *config_toml, char **out);
+
+// Releases a string returned by `afmt_format`; NULL is ignored.
+//
+// # Safety
+//
+// `s` must be NULL or a string returned by `afmt_format` that was not freed yet.
+void afmt_free(char *s);
+
+// The message of the last failed `afmt_format` call on this thread, or NULL if it succeeded.
+// The string is owned by afmt and valid until the next `afmt_format` call on this thread.
+const char *afmt_last_error(void);
+
+#endif  /* AFMT_H */
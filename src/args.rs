@@ -1,12 +1,41 @@
+use crate::formatter::CommentFallback;
+use crate::range::FormatRange;
+use crate::report::EXIT_IO_ERROR;
 use clap::{Arg as ClapArg, Command};
+use std::process;
 
 #[derive(Debug)]
 pub struct Args {
-    pub path: String,
+    pub paths: Vec<String>,
     pub config: Option<String>,
+    pub no_config: bool,
+    pub stdin: bool,
+    pub stdin_filepath: Option<String>,
+    pub changed_since: Option<String>,
+    pub staged: bool,
     pub write: bool,
     pub time: bool,
     pub check: bool,
+    pub diff: bool,
+    pub list_different: bool,
+    pub diff_context: usize,
+    pub jobs: Option<usize>,
+    pub no_cache: bool,
+    pub include_ignored: bool,
+    pub cache_dir: Option<String>,
+    pub range: Option<FormatRange>,
+    pub cursor_offset: Option<usize>,
+    pub recover: bool,
+    pub verify: bool,
+    pub verify_idempotency: bool,
+    pub comment_fallback: CommentFallback,
+    pub message_format: MessageFormat,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MessageFormat {
+    Human,
+    Json, // one JSON record per file on stdout
 }
 
 pub fn get_args() -> Args {
@@ -17,10 +46,11 @@ pub fn get_args() -> Args {
         .about(format!("Apex format tool (afmt): {}", version))
         .arg_required_else_help(true)
         .arg(
-            ClapArg::new("file")
-                .value_name("FILE")
-                .help("The relative path to the file to parse")
-                .required(true)
+            ClapArg::new("paths")
+                .value_name("PATH")
+                .help("Files, directories or glob patterns to format (directories are searched recursively); `-` reads from stdin")
+                .required_unless_present_any(["stdin", "changed-since", "staged"])
+                .num_args(1..)
                 .index(1),
         )
         .arg(
@@ -28,13 +58,47 @@ pub fn get_args() -> Args {
                 .short('c')
                 .long("config")
                 .value_name("CONFIG")
-                .help("Path to the .afmt.toml configuration file"),
+                .help("Path to the .afmt.toml configuration file [default: the nearest .afmt.toml of each file]"),
+        )
+        .arg(
+            ClapArg::new("no-config")
+                .long("no-config")
+                .help("Ignore .afmt.toml files and use the default configuration")
+                .conflicts_with("config")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            ClapArg::new("stdin")
+                .long("stdin")
+                .help("Read the source from stdin and print the result to stdout")
+                .conflicts_with_all(["paths", "write"])
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            ClapArg::new("stdin-filepath")
+                .long("stdin-filepath")
+                .value_name("PATH")
+                .help("Path of the file read from stdin, used for config discovery and error messages"),
+        )
+        .arg(
+            ClapArg::new("changed-since")
+                .long("changed-since")
+                .value_name("REF")
+                .help("Only format the files changed since the merge base with the git REF; PATHs limit the search")
+                .conflicts_with_all(["stdin", "staged"]),
+        )
+        .arg(
+            ClapArg::new("staged")
+                .long("staged")
+                .help("Only format the staged content of files; --write stages the result and leaves unstaged changes alone")
+                .conflicts_with("stdin")
+                .action(clap::ArgAction::SetTrue),
         )
         .arg(
             ClapArg::new("write")
                 .short('w')
                 .long("write")
-                .help("Write the formatted result back to the file")
+                .help("Write the formatted result back to the files")
                 .action(clap::ArgAction::SetTrue),
         )
         .arg(
@@ -52,6 +116,120 @@ pub fn get_args() -> Args {
                 .conflicts_with("write")
                 .action(clap::ArgAction::SetTrue),
         )
+        .arg(
+            ClapArg::new("diff")
+                .short('d')
+                .long("diff")
+                .help("Print a unified diff of the changes instead of the formatted files; exit with non-zero status if any")
+                .conflicts_with_all(["write", "check"])
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            ClapArg::new("list-different")
+                .short('l')
+                .long("list-different")
+                .help("Only print the paths of the files whose formatting differs; exit with non-zero status if any, unless they are written")
+                .conflicts_with_all(["check", "diff"])
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            ClapArg::new("diff-context")
+                .long("diff-context")
+                .value_name("LINES")
+                .help("Number of context lines shown around each change in --diff")
+                .value_parser(clap::value_parser!(u16))
+                .default_value("3"),
+        )
+        .arg(
+            ClapArg::new("range-start")
+                .long("range-start")
+                .value_name("BYTE")
+                .help("Only format statements and members from this byte offset on")
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            ClapArg::new("range-end")
+                .long("range-end")
+                .value_name("BYTE")
+                .help("Only format statements and members before this byte offset")
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            ClapArg::new("lines")
+                .long("lines")
+                .value_name("FIRST:LAST")
+                .help("Only format statements and members touching these lines, e.g. 10:40")
+                .conflicts_with_all(["range-start", "range-end"])
+                .value_parser(parse_lines),
+        )
+        .arg(
+            ClapArg::new("cursor-offset")
+                .long("cursor-offset")
+                .value_name("BYTE")
+                .help("Report where this byte offset of the input ends up in the formatted code (in --message-format json)")
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            ClapArg::new("recover")
+                .long("recover")
+                .help("Keep statements and members that cannot be formatted as written and report them as warnings")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            ClapArg::new("verify")
+                .long("verify")
+                .help("Re-parse the formatted code and fail if its tokens or comments differ from the input")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            ClapArg::new("verify-idempotency")
+                .long("verify-idempotency")
+                .help("Format the result a second time and fail with a diff if that changes it again")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            ClapArg::new("comment-fallback")
+                .long("comment-fallback")
+                .value_name("MODE")
+                .help("What to do when formatting would erase a comment: fail the file, reattach the comment before its statement or member, or leave the file unformatted")
+                .value_parser(["fail", "reattach", "leave"])
+                .default_value("fail"),
+        )
+        .arg(
+            ClapArg::new("jobs")
+                .short('j')
+                .long("jobs")
+                .value_name("N")
+                .help("Number of files formatted in parallel [default: number of CPUs]")
+                .value_parser(clap::value_parser!(u16).range(1..)),
+        )
+        .arg(
+            ClapArg::new("no-cache")
+                .long("no-cache")
+                .help("Format every file, even those a previous run found to be formatted")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            ClapArg::new("include-ignored")
+                .long("include-ignored")
+                .help("Format the files named on the command line even when ignored or excluded")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            ClapArg::new("cache-dir")
+                .long("cache-dir")
+                .value_name("DIR")
+                .help("Directory of the cache of formatted files [default: $AFMT_CACHE_DIR or the user cache directory]")
+                .conflicts_with("no-cache"),
+        )
+        .arg(
+            ClapArg::new("message-format")
+                .long("message-format")
+                .value_name("FORMAT")
+                .help("Output format of the per-file results")
+                .value_parser(["human", "json"])
+                .default_value("human"),
+        )
         .after_help(
             "EXAMPLES:\n\
              \n\
@@ -69,18 +247,133 @@ pub fn get_args() -> Args {
              \n\
              # Verify if the file is already formatted\n\
              afmt --check ./file.cls\n\
+             \n\
+             # Format an editor buffer piped through stdin\n\
+             cat file.cls | afmt --stdin-filepath file.cls -\n\
+             \n\
+             # Only format the statements on lines 10 to 40\n\
+             afmt --write --lines 10:40 ./file.cls\n\
+             \n\
+             # List the files that need formatting, e.g. in a pre-commit hook\n\
+             afmt --list-different force-app/\n\
+             \n\
+             # Check only the files a branch changed compared to main\n\
+             afmt --check --changed-since origin/main\n\
+             \n\
+             # Format and re-stage the staged files in a pre-commit hook\n\
+             afmt --staged --write\n\
+             \n\
+             # Show what would change as a unified diff\n\
+             afmt --diff ./file.cls\n\
+             \n\
+             # Format every .cls and .trigger file under a directory\n\
+             afmt --write force-app/\n\
+             \n\
+             # Check files matching a glob pattern\n\
+             afmt --check 'force-app/**/classes/*.cls'\n\
+             \n\
+             # Refuse to write files whose code would change beyond layout\n\
+             afmt --write --verify force-app/\n\
+             \n\
+             # Catch code that does not format stably before committing it\n\
+             afmt --check --verify-idempotency force-app/\n\
+             \n\
+             # Emit one JSON record per file for editors and CI\n\
+             afmt --check --message-format json force-app/\n\
+             \n\
+             EXIT CODES:\n\
+             0  success\n\
+             1  files need formatting (--check, --diff, --list-different)\n\
+             2  a file could not be parsed or formatted\n\
+             3  I/O, config or usage error\n\
             ",
         )
-        .get_matches();
+        .try_get_matches()
+        .unwrap_or_else(|e| {
+            if !e.use_stderr() {
+                e.exit(); // --help and --version
+            }
+            let _ = e.print();
+            process::exit(EXIT_IO_ERROR);
+        });
+
+    let range_start = matches.get_one::<usize>("range-start");
+    let range_end = matches.get_one::<usize>("range-end");
+    let range = match (
+        range_start,
+        range_end,
+        matches.get_one::<FormatRange>("lines"),
+    ) {
+        (_, _, Some(lines)) => Some(lines.clone()),
+        (None, None, None) => None,
+        (start, end, None) => Some(FormatRange::Bytes {
+            start: start.copied().unwrap_or(0),
+            end: end.copied().unwrap_or(usize::MAX),
+        }),
+    };
+
+    let paths: Vec<String> = matches
+        .get_many::<String>("paths")
+        .map(|paths| paths.cloned().collect())
+        .unwrap_or_default();
 
     Args {
-        path: matches
-            .get_one::<String>("file")
-            .expect("File path is required")
-            .to_string(),
+        stdin: matches.get_flag("stdin") || paths.iter().any(|p| p == "-"),
+        paths,
         config: matches.get_one::<String>("config").map(|s| s.to_string()),
+        no_config: matches.get_flag("no-config"),
+        stdin_filepath: matches
+            .get_one::<String>("stdin-filepath")
+            .map(|s| s.to_string()),
+        changed_since: matches
+            .get_one::<String>("changed-since")
+            .map(|s| s.to_string()),
+        staged: matches.get_flag("staged"),
         write: matches.get_flag("write"),
         time: matches.get_flag("time"),
         check: matches.get_flag("check"),
+        diff: matches.get_flag("diff"),
+        list_different: matches.get_flag("list-different"),
+        diff_context: *matches
+            .get_one::<u16>("diff-context")
+            .expect("diff-context has a default value") as usize,
+        jobs: matches.get_one::<u16>("jobs").map(|n| *n as usize),
+        no_cache: matches.get_flag("no-cache"),
+        include_ignored: matches.get_flag("include-ignored"),
+        cache_dir: matches
+            .get_one::<String>("cache-dir")
+            .map(|s| s.to_string()),
+        range,
+        cursor_offset: matches.get_one::<usize>("cursor-offset").copied(),
+        recover: matches.get_flag("recover"),
+        verify: matches.get_flag("verify"),
+        verify_idempotency: matches.get_flag("verify-idempotency"),
+        comment_fallback: match matches
+            .get_one::<String>("comment-fallback")
+            .map(|s| s.as_str())
+        {
+            Some("reattach") => CommentFallback::Reattach,
+            Some("leave") => CommentFallback::Leave,
+            _ => CommentFallback::Fail,
+        },
+        message_format: match matches
+            .get_one::<String>("message-format")
+            .map(|s| s.as_str())
+        {
+            Some("json") => MessageFormat::Json,
+            _ => MessageFormat::Human,
+        },
+    }
+}
+
+fn parse_lines(value: &str) -> Result<FormatRange, String> {
+    let invalid = || format!("expected FIRST:LAST line numbers, got `{}`", value);
+    let (first, last) = value.split_once(':').ok_or_else(invalid)?;
+    let first: usize = first.trim().parse().map_err(|_| invalid())?;
+    let last: usize = last.trim().parse().map_err(|_| invalid())?;
+
+    if first == 0 || last < first {
+        return Err(invalid());
     }
+    Ok(FormatRange::Lines { first, last })
 }
@@ -0,0 +1,10 @@
+use lsp_server::Connection;
+use std::error::Error;
+
+// Language server for editors, speaking LSP over stdio.
+fn main() -> Result<(), Box<dyn Error + Sync + Send>> {
+    let (connection, io_threads) = Connection::stdio();
+    sf_afmt::lsp::run(connection)?;
+    io_threads.join()?;
+    Ok(())
+}
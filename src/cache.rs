@@ -0,0 +1,197 @@
+use crate::formatter::{CommentFallback, Config, FormatOptions};
+use crate::range::FormatRange;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+const CACHE_DIR_ENV: &str = "AFMT_CACHE_DIR";
+// Entries kept when the file is compacted; a few MB at most.
+const MAX_ENTRIES: usize = 100_000;
+
+// Hashes of sources known to be formatted, one hex hash per line in `<dir>/afmt-<version>.cache`.
+// Each afmt version starts with an empty cache, and keys include the build of the running
+// executable, so output changes between versions or local builds are picked up.
+#[derive(Debug)]
+pub struct Cache {
+    file: PathBuf,
+    build: u64,
+    known: HashSet<u64>,
+    lines: usize, // in the file, duplicates appended by concurrent runs included
+    added: Mutex<Vec<u64>>,
+}
+
+impl Cache {
+    // A missing or unreadable cache file is an empty cache.
+    pub fn load(dir: &Path) -> Self {
+        let file = dir.join(format!("afmt-{}.cache", env!("CARGO_PKG_VERSION")));
+        let content = fs::read_to_string(&file).unwrap_or_default();
+        let known = content
+            .lines()
+            .filter_map(|line| u64::from_str_radix(line, 16).ok())
+            .collect();
+
+        Self {
+            file,
+            build: build_identity(),
+            known,
+            lines: content.lines().count(),
+            added: Mutex::default(),
+        }
+    }
+
+    // `$AFMT_CACHE_DIR`, else the user cache directory of the platform.
+    pub fn default_dir() -> Option<PathBuf> {
+        if let Some(dir) = std::env::var_os(CACHE_DIR_ENV) {
+            return Some(PathBuf::from(dir));
+        }
+        let user_cache = if cfg!(windows) {
+            std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+        } else {
+            std::env::var_os("XDG_CACHE_HOME")
+                .map(PathBuf::from)
+                .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+        };
+        user_cache.map(|dir| dir.join("afmt"))
+    }
+
+    // Identifies `source_code` formatted with `config` and `options` by this build. The cursor
+    // offset is left out: it does not change the code, and cache hits map it on their own.
+    pub fn key(&self, source_code: &str, config: &Config, options: &FormatOptions) -> u64 {
+        let mut hash = StableHash::default();
+        hash.u64(self.build);
+        hash.bytes(source_code.as_bytes());
+        hash.u64(config.max_width.into());
+        hash.u64(config.indent_size.into());
+        match &options.range {
+            None => hash.u64(0),
+            Some(FormatRange::Bytes { start, end }) => {
+                hash.u64(1);
+                hash.u64(*start as u64);
+                hash.u64(*end as u64);
+            }
+            Some(FormatRange::Lines { first, last }) => {
+                hash.u64(2);
+                hash.u64(*first as u64);
+                hash.u64(*last as u64);
+            }
+        }
+        hash.u64(options.recover.into());
+        hash.u64(options.verify.into());
+        hash.u64(options.verify_idempotency.into());
+        hash.u64(match options.comment_fallback {
+            CommentFallback::Fail => 0,
+            CommentFallback::Reattach => 1,
+            CommentFallback::Leave => 2,
+        });
+        hash.finish()
+    }
+
+    pub fn is_formatted(&self, key: u64) -> bool {
+        self.known.contains(&key) || self.added.lock().unwrap().contains(&key)
+    }
+
+    pub fn insert(&self, key: u64) {
+        let mut added = self.added.lock().unwrap();
+        if !self.known.contains(&key) && !added.contains(&key) {
+            added.push(key);
+        }
+    }
+
+    // Append the hashes added since `load()`; other afmt runs may have appended theirs meanwhile.
+    // Once the file outgrows `MAX_ENTRIES`, it is rewritten with the newest entries only.
+    pub fn save(&self) -> Result<(), String> {
+        let added = self.added.lock().unwrap();
+        if added.is_empty() {
+            return Ok(());
+        }
+
+        let dir = self.file.parent().unwrap_or(Path::new("."));
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create cache directory {}: {}", dir.display(), e))?;
+
+        if self.lines + added.len() > MAX_ENTRIES {
+            return self.compact(&added);
+        }
+
+        let lines: String = added.iter().map(|key| format!("{:016x}\n", key)).collect();
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.file)
+            .map_err(|e| format!("Failed to open cache {}: {}", self.file.display(), e))?;
+        std::io::Write::write_all(&mut file, lines.as_bytes())
+            .map_err(|e| format!("Failed to write cache {}: {}", self.file.display(), e))
+    }
+
+    // Replace the file with its newest unique entries, through a rename so that concurrent runs
+    // read either the old file or the new one.
+    fn compact(&self, added: &[u64]) -> Result<(), String> {
+        let content = fs::read_to_string(&self.file).unwrap_or_default();
+        let mut seen = HashSet::new();
+        let mut newest: Vec<u64> = content
+            .lines()
+            .filter_map(|line| u64::from_str_radix(line, 16).ok())
+            .chain(added.iter().copied())
+            .rev()
+            .filter(|key| seen.insert(*key))
+            .take(MAX_ENTRIES / 2)
+            .collect();
+        newest.reverse();
+
+        let lines: String = newest.iter().map(|key| format!("{:016x}\n", key)).collect();
+        let temp = self
+            .file
+            .with_extension(format!("cache.{}", std::process::id()));
+        fs::write(&temp, lines)
+            .and_then(|_| fs::rename(&temp, &self.file))
+            .map_err(|e| format!("Failed to write cache {}: {}", self.file.display(), e))
+    }
+}
+
+// Tells apart builds of the same version by the size and modification time of the executable.
+fn build_identity() -> u64 {
+    let mut hash = StableHash::default();
+    hash.bytes(env!("CARGO_PKG_VERSION").as_bytes());
+    let metadata = std::env::current_exe().and_then(fs::metadata);
+    if let Ok(metadata) = metadata {
+        hash.u64(metadata.len());
+        let modified = metadata.modified().ok();
+        let since_epoch = modified.and_then(|time| time.duration_since(UNIX_EPOCH).ok());
+        hash.u64(since_epoch.map_or(0, |d| d.as_nanos() as u64));
+    }
+    hash.finish()
+}
+
+// 64-bit FNV-1a, which unlike the std hasher gives the same hash in every build and platform.
+struct StableHash(u64);
+
+impl Default for StableHash {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl StableHash {
+    fn bytes(&mut self, bytes: &[u8]) {
+        // the length keeps consecutive fields apart
+        self.u64(bytes.len() as u64);
+        self.write(bytes);
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.write(&value.to_le_bytes());
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
@@ -1,18 +1,192 @@
-use std::{cell::Cell, collections::HashMap};
-use tree_sitter::Node;
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    ops::Deref,
+    rc::Rc,
+};
+use tree_sitter::{Node, Range, Tree};
 
 use crate::{
-    accessor::Accessor,
     data_model::DocBuild,
     doc::DocRef,
     doc_builder::DocBuilder,
+    error::{ErrorLocation, FormatWarning, SyntaxDiagnostic},
+    suppression,
     utility::{
-        get_comment_bucket, is_bracket_composite_node, is_punctuation_node, panic_unknown_node,
+        collect_comments, get_comment_bucket, is_bracket_composite_node, is_punctuation_node,
     },
 };
 
 pub type CommentMap = HashMap<usize, CommentBucket>;
 
+// Nodes whose children are built as `BodyMember`s, which recovery mode can emit verbatim.
+const RECOVERY_CONTAINERS: [&str; 7] = [
+    "parser_output",
+    "class_body",
+    "interface_body",
+    "enum_body",
+    "constructor_body",
+    "trigger_body",
+    "block",
+];
+
+thread_local! {
+    static CURRENT_CONTEXT: RefCell<Option<Rc<FormatContext>>> = const { RefCell::new(None) };
+}
+
+// The source code and comments of the file being formatted.
+// It is entered for the duration of one `format_one()` call, which makes it reachable from
+// `Accessor`, `get_comment_bucket()` and the `DocBuild` impls without leaking it.
+#[derive(Debug)]
+pub struct FormatContext {
+    source_code: String,
+    comment_map: CommentMap,
+    recover: bool,
+    error_units: RefCell<HashMap<usize, String>>, // members containing ERROR or MISSING nodes
+    unrecoverable: bool,                          // a syntax error outside of any member
+    warnings: RefCell<Vec<(usize, usize, FormatWarning)>>, // with the byte range kept as written
+    off_regions: Vec<std::ops::Range<usize>>,     // kept as written, see `suppression`
+}
+
+impl FormatContext {
+    pub fn new(source_code: &str, ast_tree: &Tree, recover: bool) -> Self {
+        let mut cursor = ast_tree.walk();
+        let mut comment_map = CommentMap::new();
+        collect_comments(&mut cursor, source_code, &mut comment_map);
+
+        let mut error_units = HashMap::new();
+        let unrecoverable =
+            !collect_error_units(ast_tree.root_node(), source_code, &mut error_units);
+
+        Self {
+            source_code: source_code.to_string(),
+            comment_map,
+            recover,
+            error_units: RefCell::new(error_units),
+            unrecoverable,
+            warnings: RefCell::new(Vec::new()),
+            off_regions: suppression::off_regions(ast_tree.root_node(), source_code),
+        }
+    }
+
+    // Make this context the current one until the returned guard is dropped.
+    pub fn enter(self) -> ContextGuard {
+        let previous = CURRENT_CONTEXT.with(|current| current.borrow_mut().replace(Rc::new(self)));
+        ContextGuard { previous }
+    }
+
+    pub fn current() -> Rc<FormatContext> {
+        CURRENT_CONTEXT.with(|current| {
+            current
+                .borrow()
+                .clone()
+                .expect("## FormatContext not entered for this thread")
+        })
+    }
+
+    pub fn source_code(&self) -> &str {
+        &self.source_code
+    }
+
+    pub fn comment_map(&self) -> &CommentMap {
+        &self.comment_map
+    }
+
+    pub fn recover(&self) -> bool {
+        self.recover
+    }
+
+    // The first syntax error in `node` if it is a member containing one; each is taken once.
+    pub fn take_error_unit(&self, node: &Node) -> Option<String> {
+        self.error_units.borrow_mut().remove(&node.id())
+    }
+
+    // The `afmt:off` region `node` starts in, if any.
+    pub fn off_region(&self, node: &Node) -> Option<std::ops::Range<usize>> {
+        let start = node.start_byte();
+        self.off_regions
+            .iter()
+            .find(|r| r.contains(&start))
+            .cloned()
+    }
+
+    // Whether some syntax errors were not covered by members emitted verbatim.
+    pub fn has_unrecovered_errors(&self) -> bool {
+        self.unrecoverable || !self.error_units.borrow().is_empty()
+    }
+
+    pub fn warn(&self, byte_range: std::ops::Range<usize>, message: &str) {
+        let text = &self.source_code[byte_range.clone()];
+        let warning = FormatWarning {
+            message: message.to_string(),
+            location: ErrorLocation::at(
+                &self.source_code,
+                byte_range.start,
+                text.lines().next().unwrap_or_default(),
+            ),
+        };
+        let entry = (byte_range.start, byte_range.end, warning);
+        self.warnings.borrow_mut().push(entry);
+    }
+
+    // Warnings in source order, without those inside another region kept as written.
+    pub fn take_warnings(&self) -> Vec<FormatWarning> {
+        let mut warnings = self.warnings.take();
+        warnings.sort_by_key(|(start, end, _)| (*start, std::cmp::Reverse(*end)));
+
+        let mut result: Vec<FormatWarning> = Vec::new();
+        let mut covered_until = 0;
+        for (start, end, warning) in warnings {
+            if start >= covered_until {
+                covered_until = end;
+                result.push(warning);
+            }
+        }
+        result
+    }
+}
+
+// Record the closest member around each syntax error; false if one is not inside any member.
+fn collect_error_units(node: Node, source_code: &str, units: &mut HashMap<usize, String>) -> bool {
+    if node.is_error() || node.is_missing() {
+        let mut current = Some(node);
+        while let Some(n) = current {
+            let parent = n.parent();
+            let is_member = n.is_named()
+                && (!n.is_extra() || n.is_error())
+                && parent.is_some_and(|p| RECOVERY_CONTAINERS.contains(&p.kind()));
+            if is_member {
+                let diagnostic = SyntaxDiagnostic::from_node(&node, source_code);
+                units.entry(n.id()).or_insert(diagnostic.message);
+                return true;
+            }
+            current = parent;
+        }
+        return false;
+    }
+
+    let mut cursor = node.walk();
+    let children: Vec<Node> = node
+        .children(&mut cursor)
+        .filter(|c| c.has_error())
+        .collect();
+    children
+        .into_iter()
+        .all(|child| collect_error_units(child, source_code, units))
+}
+
+// Restores the previously entered context (if any) on drop, also when unwinding.
+pub struct ContextGuard {
+    previous: Option<Rc<FormatContext>>,
+}
+
+impl Drop for ContextGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CURRENT_CONTEXT.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
 #[derive(Debug)]
 pub struct NodeContext {
     pub id: usize,
@@ -62,6 +236,29 @@ impl CommentBucket {
     }
 }
 
+// A handle to one node's bucket that keeps its `FormatContext` alive while in use.
+pub struct CommentBucketRef {
+    context: Rc<FormatContext>,
+    node_id: usize,
+}
+
+impl CommentBucketRef {
+    pub fn new(context: Rc<FormatContext>, node_id: usize) -> Self {
+        if !context.comment_map.contains_key(&node_id) {
+            panic!("## comment_map missing bucket for node: {}", node_id);
+        }
+        Self { context, node_id }
+    }
+}
+
+impl Deref for CommentBucketRef {
+    type Target = CommentBucket;
+
+    fn deref(&self) -> &CommentBucket {
+        &self.context.comment_map[&self.node_id]
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum CommentType {
     Line,
@@ -74,13 +271,18 @@ pub struct Comment {
     pub value: String,
     pub comment_type: CommentType,
     pub metadata: CommentMetadata,
+    pub range: Range,
     pub is_printed: Cell<bool>,
 }
 
 impl Comment {
-    pub fn from_node(node: Node) -> Self {
+    pub fn from_node(node: Node, source_code: &str) -> Self {
         //let id = node.id();
-        let value = node.value().trim_end().to_string();
+        let value = node
+            .utf8_text(source_code.as_bytes())
+            .expect("comment node must be valid utf8")
+            .trim_end()
+            .to_string();
         let (comment_type, metadata) = match node.kind() {
             "line_comment" => {
                 let metadata = CommentMetadata::from(&node, CommentType::Line);
@@ -90,7 +292,8 @@ impl Comment {
                 let metadata = CommentMetadata::from(&node, CommentType::Block);
                 (CommentType::Block, metadata)
             }
-            _ => panic_unknown_node(node, "Comment"),
+            // `is_comment_node()` lets only comments through
+            kind => unreachable!("## not a comment node: {}", kind),
         };
 
         Self {
@@ -98,6 +301,7 @@ impl Comment {
             value,
             comment_type,
             metadata,
+            range: node.range(),
             is_printed: Cell::new(false),
         }
     }
@@ -301,7 +505,8 @@ impl Punctuation {
                 type_: PuncuationType::Semicolon,
                 id: node.id(),
             },
-            _ => panic_unknown_node(node, "Puncuation"),
+            // callers check `is_punctuation_node()` first
+            kind => unreachable!("## not a punctuation node: {}", kind),
         }
     }
 
@@ -309,7 +514,8 @@ impl Punctuation {
     pub fn from(node: &Node) -> Option<Self> {
         let mut current = *node;
         while let Some(next) = current.next_sibling() {
-            if next.is_extra() {
+            // a punctuation after an ERROR node belongs to that node
+            if next.is_extra() && !next.is_error() {
                 current = next;
                 continue;
             }
@@ -0,0 +1,89 @@
+use crate::verify::{collect_tokens, Token};
+use tree_sitter::Tree;
+
+// The offset in `formatted_code` matching byte `offset` of `source_code`. Tokens keep their order
+// when formatted (see `verify`), so the n-th token of both trees is the same one: a cursor inside
+// a token stays at the same place in it, a cursor in whitespace follows the token before it.
+// Code and comments are matched separately, as comments may move around punctuation.
+// None when the tokens of both trees do not match, as no offset would be right then.
+pub fn map_offset(
+    source_code: &str,
+    source_tree: &Tree,
+    formatted_code: &str,
+    formatted_tree: &Tree,
+    offset: usize,
+) -> Option<usize> {
+    if offset >= source_code.len() {
+        return Some(formatted_code.len());
+    }
+    if source_code == formatted_code {
+        return Some(offset);
+    }
+
+    let (source_tokens, source_comments) = collect_tokens(source_tree.root_node(), source_code);
+    let (formatted_tokens, formatted_comments) =
+        collect_tokens(formatted_tree.root_node(), formatted_code);
+
+    let code = locate(&source_tokens, offset);
+    let comment = locate(&source_comments, offset);
+    let mapped = match (code, comment) {
+        (_, Some(comment @ Anchor::Inside { .. })) => {
+            comment.map(&source_comments, &formatted_comments)
+        }
+        (Some(Anchor::After { end, .. }), Some(comment @ Anchor::After { end: after, .. }))
+            if after > end =>
+        {
+            comment.map(&source_comments, &formatted_comments)
+        }
+        (Some(code), _) => code.map(&source_tokens, &formatted_tokens),
+        (None, Some(comment)) => comment.map(&source_comments, &formatted_comments),
+        // in the whitespace before the first token
+        (None, None) => Some(0),
+    };
+
+    let mut mapped = mapped?.min(formatted_code.len());
+    while !formatted_code.is_char_boundary(mapped) {
+        mapped -= 1;
+    }
+    Some(mapped)
+}
+
+// Where the cursor is relative to a token.
+#[derive(Clone, Copy, Debug)]
+enum Anchor {
+    Inside { index: usize, delta: usize }, // `delta` bytes into the token
+    After { index: usize, end: usize },    // in the whitespace after it, which ends at `end`
+}
+
+impl Anchor {
+    fn map(self, from: &[Token], to: &[Token]) -> Option<usize> {
+        let index = match self {
+            Self::Inside { index, .. } | Self::After { index, .. } => index,
+        };
+        let (expected, found) = (from.get(index)?, to.get(index)?);
+        if from.len() != to.len() || expected.normalized != found.normalized {
+            return None;
+        }
+        Some(match self {
+            Self::Inside { delta, .. } => found.start_byte + delta.min(found.text.len()),
+            Self::After { .. } => found.start_byte + found.text.len(),
+        })
+    }
+}
+
+// The token containing `offset`, else the last one before it.
+fn locate(tokens: &[Token], offset: usize) -> Option<Anchor> {
+    let index = tokens
+        .partition_point(|token| token.start_byte <= offset)
+        .checked_sub(1)?;
+    let token = &tokens[index];
+    let end = token.start_byte + token.text.len();
+    if offset < end {
+        Some(Anchor::Inside {
+            index,
+            delta: offset - token.start_byte,
+        })
+    } else {
+        Some(Anchor::After { index, end })
+    }
+}
@@ -1,10 +1,10 @@
 use crate::{
     accessor::Accessor,
-    context::{NodeContext, Punctuation},
+    context::{FormatContext, NodeContext, Punctuation},
     doc::DocRef,
     doc_builder::{DocBuilder, Insertable},
     enum_def::*,
-    message_helper::red,
+    error::FormatError,
     utility::*,
 };
 use std::fmt::Debug;
@@ -27,21 +27,18 @@ pub struct Root {
 }
 
 impl Root {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "parser_output");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "parser_output")?;
 
-        let members: Vec<_> = node
-            .children_vec()
-            .into_iter()
-            .map(|n| BodyMember::new(&n, RootMember::new(n)))
-            .collect();
+        let members: Vec<_> =
+            BodyMember::collect(node.children_vec(), RootMember::new, RootMember::Verbatim)?;
 
         let node_context = NodeContext::with_punctuation(&node);
 
-        Self {
+        Ok(Self {
             members,
             node_context,
-        }
+        })
     }
 }
 
@@ -75,20 +72,30 @@ pub struct ClassDeclaration {
 }
 
 impl ClassDeclaration {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "class_declaration");
-
-        Self {
-            modifiers: node.try_c_by_k("modifiers").map(|n| Modifiers::new(n)),
-            name: ValueNode::new(node.c_by_n("name")),
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "class_declaration")?;
+
+        Ok(Self {
+            modifiers: node
+                .try_c_by_k("modifiers")
+                .map(Modifiers::new)
+                .transpose()?,
+            name: ValueNode::new(node.c_by_n("name")?)?,
             type_parameters: node
                 .try_c_by_k("type_parameters")
-                .map(|n| TypeParameters::new(n)),
-            superclass: node.try_c_by_k("superclass").map(|n| SuperClass::new(n)),
-            interface: node.try_c_by_k("interfaces").map(|n| Interface::new(n)),
-            body: ClassBody::new(node.c_by_n("body")),
+                .map(TypeParameters::new)
+                .transpose()?,
+            superclass: node
+                .try_c_by_k("superclass")
+                .map(SuperClass::new)
+                .transpose()?,
+            interface: node
+                .try_c_by_k("interfaces")
+                .map(Interface::new)
+                .transpose()?,
+            body: ClassBody::new(node.c_by_n("body")?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -143,17 +150,20 @@ pub struct MethodDeclaration {
 }
 
 impl MethodDeclaration {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "method_declaration");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "method_declaration")?;
 
-        Self {
-            modifiers: node.try_c_by_k("modifiers").map(|n| Modifiers::new(n)),
-            type_: UnannotatedType::new(node.c_by_n("type")),
-            name: ValueNode::new(node.c_by_n("name")),
-            formal_parameters: FormalParameters::new(node.c_by_n("parameters")),
-            body: node.try_c_by_n("body").map(|n| Block::new(n)),
+        Ok(Self {
+            modifiers: node
+                .try_c_by_k("modifiers")
+                .map(Modifiers::new)
+                .transpose()?,
+            type_: UnannotatedType::new(node.c_by_n("type")?)?,
+            name: ValueNode::new(node.c_by_n("name")?)?,
+            formal_parameters: FormalParameters::new(node.c_by_n("parameters")?)?,
+            body: node.try_c_by_n("body").map(Block::new).transpose()?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -185,19 +195,19 @@ pub struct FormalParameters {
 }
 
 impl FormalParameters {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "formal_parameters");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "formal_parameters")?;
 
         let formal_parameters = node
             .try_cs_by_k("formal_parameter")
             .into_iter()
             .map(FormalParameter::new)
-            .collect();
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             formal_parameters,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -225,16 +235,22 @@ pub struct FormalParameter {
 }
 
 impl FormalParameter {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "formal_parameter");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "formal_parameter")?;
 
-        Self {
-            modifiers: node.try_c_by_k("modifiers").map(Modifiers::new),
-            type_: UnannotatedType::new(node.c_by_n("type")),
-            name: ValueNode::new(node.c_by_n("name")),
-            dimensions: node.try_c_by_k("dimensions").map(Dimensions::new),
+        Ok(Self {
+            modifiers: node
+                .try_c_by_k("modifiers")
+                .map(Modifiers::new)
+                .transpose()?,
+            type_: UnannotatedType::new(node.c_by_n("type")?)?,
+            name: ValueNode::new(node.c_by_n("name")?)?,
+            dimensions: node
+                .try_c_by_k("dimensions")
+                .map(Dimensions::new)
+                .transpose()?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -262,13 +278,13 @@ pub struct SuperClass {
 }
 
 impl SuperClass {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "superclass");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "superclass")?;
 
-        Self {
-            type_: Type::new(node.first_c()),
+        Ok(Self {
+            type_: Type::new(node.first_c()?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -289,26 +305,26 @@ pub struct Modifiers {
 }
 
 impl Modifiers {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "modifiers");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "modifiers")?;
 
         let annotations = node
             .try_cs_by_k("annotation")
             .into_iter()
             .map(Annotation::new)
-            .collect();
+            .collect::<Result<_, _>>()?;
 
         let modifiers = node
             .try_cs_by_k("modifier")
             .into_iter()
             .map(Modifier::new)
-            .collect();
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             annotations,
             modifiers,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -334,13 +350,13 @@ pub struct Modifier {
 }
 
 impl Modifier {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "modifier");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "modifier")?;
 
-        Self {
-            kind: ModifierKind::new(node.first_c()),
+        Ok(Self {
+            kind: ModifierKind::new(node.first_c()?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -360,18 +376,19 @@ pub struct Annotation {
 }
 
 impl Annotation {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "annotation");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "annotation")?;
 
         let arguments = node
             .try_c_by_n("arguments")
-            .map(AnnotationArgumentList::new);
+            .map(AnnotationArgumentList::new)
+            .transpose()?;
 
-        Self {
-            name: ValueNode::new(node.c_by_n("name")),
+        Ok(Self {
+            name: ValueNode::new(node.c_by_n("name")?)?,
             arguments,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -397,14 +414,14 @@ pub struct AnnotationKeyValue {
 }
 
 impl AnnotationKeyValue {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "annotation_key_value");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "annotation_key_value")?;
 
-        Self {
-            key: ValueNode::new(node.c_by_n("key")),
-            value: ValueNode::new(node.c_by_n("value")),
+        Ok(Self {
+            key: ValueNode::new(node.c_by_n("key")?)?,
+            value: ValueNode::new(node.c_by_n("value")?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -425,33 +442,30 @@ pub struct ClassBody {
 }
 
 impl ClassBody {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "class_body");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "class_body")?;
 
-        let class_members: Vec<_> = node
-            .children_vec()
-            .into_iter()
-            .map(|n| BodyMember::new(&n, ClassMember::new(n)))
-            .collect();
+        let class_members: Vec<_> =
+            BodyMember::collect(node.children_vec(), ClassMember::new, ClassMember::Verbatim)?;
         let node_context = NodeContext::with_punctuation(&node);
 
-        Self {
+        Ok(Self {
             class_members,
             node_context,
-        }
+        })
     }
 }
 
 impl<'a> DocBuild<'a> for ClassBody {
     fn build_inner(&self, b: &'a DocBuilder<'a>, result: &mut Vec<DocRef<'a>>) {
         let bucket = get_comment_bucket(&self.node_context.id);
-        handle_pre_comments(b, bucket, result);
+        handle_pre_comments(b, &bucket, result);
 
         if bucket.dangling_comments.is_empty() {
             result.push(b.surround_body_members(&self.class_members, "{", "}"));
-            handle_post_comments(b, bucket, result);
+            handle_post_comments(b, &bucket, result);
         } else {
-            handle_dangling_comments_in_bracket_surround(b, bucket, result);
+            handle_dangling_comments_in_bracket_surround(b, &bucket, result);
         }
     }
 }
@@ -466,28 +480,32 @@ pub struct FieldDeclaration {
 }
 
 impl FieldDeclaration {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "field_declaration");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "field_declaration")?;
 
-        let modifiers = node.try_c_by_k("modifiers").map(|n| Modifiers::new(n));
+        let modifiers = node
+            .try_c_by_k("modifiers")
+            .map(Modifiers::new)
+            .transpose()?;
 
         let declarators = node
-            .cs_by_n("declarator")
+            .cs_by_n("declarator")?
             .into_iter()
-            .map(|n| VariableDeclarator::new(n))
-            .collect();
+            .map(VariableDeclarator::new)
+            .collect::<Result<_, _>>()?;
 
         let accessor_list = node
             .try_c_by_k("accessor_list")
-            .map(|n| AccessorList::new(n));
+            .map(AccessorList::new)
+            .transpose()?;
 
-        Self {
+        Ok(Self {
             modifiers,
-            type_: UnannotatedType::new(node.c_by_n("type")),
+            type_: UnannotatedType::new(node.c_by_n("type")?)?,
             declarators,
             accessor_list,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -527,19 +545,19 @@ pub struct ArrayInitializer {
 }
 
 impl ArrayInitializer {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "array_initializer");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "array_initializer")?;
 
         let initializers: Vec<_> = node
             .children_vec()
             .into_iter()
-            .map(|n| VariableInitializer::new(n))
-            .collect();
+            .map(VariableInitializer::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             initializers,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -567,18 +585,18 @@ pub struct AssignmentExpression {
 }
 
 impl AssignmentExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "assignment_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "assignment_expression")?;
 
-        let right_child = node.c_by_n("right");
+        let right_child = node.c_by_n("right")?;
 
-        Self {
-            left: AssignmentLeft::new(node.c_by_n("left")),
-            op: ValueNode::new(node.c_by_n("operator")),
-            right: Expression::new(right_child),
+        Ok(Self {
+            left: AssignmentLeft::new(node.c_by_n("left")?)?,
+            op: ValueNode::new(node.c_by_n("operator")?)?,
+            right: Expression::new(right_child)?,
             is_right_child_a_query_node: is_query_expression(&right_child),
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -607,13 +625,13 @@ pub enum AssignmentLeft {
 }
 
 impl AssignmentLeft {
-    pub fn new(node: Node) -> Self {
-        match node.kind() {
-            "identifier" => Self::Identifier(ValueNode::new(node)),
-            "field_access" => Self::Field(FieldAccess::new(node)),
-            "array_access" => Self::Array(ArrayAccess::new(node)),
-            _ => panic_unknown_node(node, "AssignmentLeft"),
-        }
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        Ok(match node.kind() {
+            "identifier" => Self::Identifier(ValueNode::new(node)?),
+            "field_access" => Self::Field(FieldAccess::new(node)?),
+            "array_access" => Self::Array(ArrayAccess::new(node)?),
+            _ => return Err(unsupported_node(node, "AssignmentLeft")),
+        })
     }
 }
 
@@ -639,12 +657,12 @@ pub struct BoolType {
 }
 
 impl BoolType {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "boolean_type");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "boolean_type")?;
 
-        Self {
+        Ok(Self {
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -663,36 +681,155 @@ pub struct Block {
 }
 
 impl Block {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "block");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "block")?;
 
-        let statements: Vec<BodyMember<Statement>> = node
-            .children_vec()
-            .into_iter()
-            .map(|n| BodyMember::new(&n, Statement::new(n)))
-            .collect();
+        let statements: Vec<BodyMember<Statement>> =
+            BodyMember::collect(node.children_vec(), Statement::new, Statement::Verbatim)?;
         let node_context = NodeContext::with_punctuation(&node);
 
-        Self {
+        Ok(Self {
             statements,
             node_context,
-        }
+        })
     }
 }
 
 impl<'a> DocBuild<'a> for Block {
     fn build_inner(&self, b: &'a DocBuilder<'a>, result: &mut Vec<DocRef<'a>>) {
         let bucket = get_comment_bucket(&self.node_context.id);
-        handle_pre_comments(b, bucket, result);
+        handle_pre_comments(b, &bucket, result);
 
         if bucket.dangling_comments.is_empty() {
             let docs = b.surround_body_members(&self.statements, "{", "}");
             result.push(docs);
         } else {
-            handle_dangling_comments_in_bracket_surround(b, bucket, result);
+            handle_dangling_comments_in_bracket_surround(b, &bucket, result);
             return;
         }
-        handle_post_comments(b, bucket, result);
+        handle_post_comments(b, &bucket, result);
+    }
+}
+
+// A member printed as written in the source, used by recovery mode and ignore comments.
+#[derive(Debug)]
+pub struct Verbatim {
+    pub lines: Vec<String>,
+    pub byte_range: std::ops::Range<usize>,
+    pub node_context: NodeContext, // of the first node
+    pub last_id: usize,            // the node whose post comments follow
+    pub exact: bool,               // continuation lines keep their indent
+}
+
+impl Verbatim {
+    pub fn new(node: Node, reason: &str) -> Self {
+        Self::spanning(node, node, reason)
+    }
+
+    // The sibling nodes from `first` to `last` and everything between them.
+    pub fn spanning(first: Node, last: Node, reason: &str) -> Self {
+        let context = FormatContext::current();
+        let byte_range = first.start_byte()..last.end_byte();
+        context.warn(byte_range.clone(), &format!("kept as written ({})", reason));
+
+        // continuation lines are re-based from the indent of the first line to the current level
+        let source_code = context.source_code();
+        let line_start = source_code[..byte_range.start]
+            .rfind('\n')
+            .map_or(0, |i| i + 1);
+        let first_line = &source_code[line_start..];
+        let base_indent = first_line.len() - first_line.trim_start_matches([' ', '\t']).len();
+
+        let lines = source_code[byte_range.clone()]
+            .lines()
+            .enumerate()
+            .map(|(i, line)| {
+                if i == 0 {
+                    return line.trim_end().to_string();
+                }
+                let indent = line.len() - line.trim_start_matches([' ', '\t']).len();
+                line[indent.min(base_indent)..].trim_end().to_string()
+            })
+            .collect();
+
+        Self {
+            lines,
+            byte_range,
+            node_context: NodeContext::without_punctuation(&first),
+            last_id: last.id(),
+            exact: false,
+        }
+    }
+
+    // Code suppressed by `afmt-ignore` or `afmt:off`: the sibling nodes from `first` to `last`
+    // within `byte_range`, which also covers the comments around them, e.g. the `afmt:off` one.
+    // Only the first line is re-indented.
+    pub fn suppressed(byte_range: std::ops::Range<usize>, first: Node, last: Node) -> Self {
+        let context = FormatContext::current();
+        let lines = context.source_code()[byte_range.clone()]
+            .lines()
+            .map(str::to_string)
+            .collect();
+
+        Self {
+            lines,
+            byte_range,
+            node_context: NodeContext::without_punctuation(&first),
+            last_id: last.id(),
+            exact: true,
+        }
+    }
+
+    // Also print the `,` following `node`, for members separated by punctuation.
+    pub fn with_punctuation(mut self, node: &Node) -> Self {
+        self.node_context.punc = Punctuation::from(node);
+        self
+    }
+}
+
+impl<'a> DocBuild<'a> for Verbatim {
+    fn build_inner(&self, b: &'a DocBuilder<'a>, result: &mut Vec<DocRef<'a>>) {
+        // the pre comments inside the range are part of the text
+        let bucket = get_comment_bucket(&self.node_context.id);
+        let before = bucket
+            .pre_comments
+            .iter()
+            .take_while(|c| c.range.end_byte <= self.byte_range.start)
+            .count();
+        handle_leading_comments(b, &bucket.pre_comments[..before], result);
+
+        for (i, line) in self.lines.iter().enumerate() {
+            if i > 0 && (line.is_empty() || self.exact) {
+                result.push(b.nl_with_no_indent());
+                if line.is_empty() {
+                    continue;
+                }
+            } else if i > 0 {
+                result.push(b.nl());
+            }
+            result.push(b.txt(line));
+        }
+
+        // comments inside the member are part of its text
+        let context = FormatContext::current();
+        for bucket in context.comment_map().values() {
+            let comments = bucket
+                .pre_comments
+                .iter()
+                .chain(&bucket.post_comments)
+                .chain(&bucket.dangling_comments);
+            for comment in comments {
+                let range = &self.byte_range;
+                if range.start <= comment.range.start_byte && comment.range.end_byte <= range.end {
+                    comment.mark_as_printed();
+                }
+            }
+        }
+
+        handle_post_comments(b, &get_comment_bucket(&self.last_id), result);
+        if let Some(ref n) = self.node_context.punc {
+            result.push(n.build(b));
+        }
     }
 }
 
@@ -703,13 +840,13 @@ pub struct Interface {
 }
 
 impl Interface {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "interfaces");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "interfaces")?;
 
-        Self {
-            type_list: TypeList::new(node.c_by_k("type_list")),
+        Ok(Self {
+            type_list: TypeList::new(node.c_by_k("type_list")?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -730,19 +867,19 @@ pub struct TypeList {
 }
 
 impl TypeList {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "type_list");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "type_list")?;
 
         let types = node
             .children_vec()
             .into_iter()
-            .map(|n| Type::new(n))
-            .collect();
+            .map(Type::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             types,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -770,12 +907,12 @@ pub enum ObjectExpression {
 }
 
 impl ObjectExpression {
-    pub fn new(node: Node) -> Self {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
         //TODO: handle incoming comment node
-        match node.kind() {
-            "super" => Self::Super(Super::new(node)),
-            _ => Self::Primary(Box::new(PrimaryExpression::new(node))),
-        }
+        Ok(match node.kind() {
+            "super" => Self::Super(Super::new(node)?),
+            _ => Self::Primary(Box::new(PrimaryExpression::new(node)?)),
+        })
     }
 }
 
@@ -868,24 +1005,25 @@ pub struct MethodInvocation {
 }
 
 impl MethodInvocation {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "method_invocation");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "method_invocation")?;
 
-        let name = ValueNode::new(node.c_by_n("name"));
-        let arguments = ArgumentList::new(node.c_by_n("arguments"));
+        let name = ValueNode::new(node.c_by_n("name")?)?;
+        let arguments = ArgumentList::new(node.c_by_n("arguments")?)?;
 
         let kind = if let Some(obj) = node.try_c_by_n("object") {
-            let object = ObjectExpression::new(obj);
-            let next_named = obj.next_named();
+            let object = ObjectExpression::new(obj)?;
+            let next_named = obj.next_named()?;
             let property_navigation = if next_named.kind() == "safe_navigation_operator" {
-                PropertyNavigation::Safe(SafeNavigationOperator::new(next_named))
+                PropertyNavigation::Safe(SafeNavigationOperator::new(next_named)?)
             } else {
                 PropertyNavigation::Dot
             };
 
             let type_arguments = node
                 .try_c_by_k("type_arguments")
-                .map(|n| TypeArguments::new(n));
+                .map(TypeArguments::new)
+                .transpose()?;
             let context = build_chaining_context(&node);
 
             MethodInvocationKind::Complex {
@@ -900,10 +1038,10 @@ impl MethodInvocation {
             MethodInvocationKind::Simple { name, arguments }
         };
 
-        Self {
+        Ok(Self {
             kind,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -941,17 +1079,17 @@ pub struct TypeArguments {
 }
 
 impl TypeArguments {
-    pub fn new(node: Node) -> Self {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
         let types = node
             .children_vec()
             .into_iter()
-            .map(|n| Type::new(n))
-            .collect();
+            .map(Type::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             types,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -975,17 +1113,17 @@ pub struct ArgumentList {
 }
 
 impl ArgumentList {
-    pub fn new(node: Node) -> Self {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
         let expressions = node
             .children_vec()
             .into_iter()
-            .map(|n| Expression::new(n))
-            .collect();
+            .map(Expression::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             expressions,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -1009,12 +1147,12 @@ pub struct Super {
 }
 
 impl Super {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "super");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "super")?;
 
-        Self {
+        Ok(Self {
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -1032,12 +1170,12 @@ pub struct This {
 }
 
 impl This {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "this");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "this")?;
 
-        Self {
+        Ok(Self {
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -1066,34 +1204,33 @@ pub struct BinaryExpression {
 }
 
 impl BinaryExpression {
-    fn build_context(node: &Node) -> BinaryExpressionContext {
-        let op = node.c_by_n("operator").kind();
-        let precedence = get_precedence(op);
+    fn build_context(node: &Node) -> Result<BinaryExpressionContext, FormatError> {
+        let precedence = get_precedence(&node.c_by_n("operator")?)?;
         let parent = node
             .parent()
             .expect("BinaryExpression node should always have a parent");
 
         let is_a_chaining_inner_node = is_binary_exp(&parent);
-        let has_parent_same_precedence = is_binary_exp(&parent)
-            && precedence == get_precedence(parent.c_by_n("operator").kind());
+        let has_parent_same_precedence =
+            is_binary_exp(&parent) && precedence == get_precedence(&parent.c_by_n("operator")?)?;
 
-        BinaryExpressionContext {
+        Ok(BinaryExpressionContext {
             has_parent_same_precedence,
             is_a_chaining_inner_node,
             is_parent_return_statement: parent.kind() == "return_statement",
-        }
+        })
     }
 
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "binary_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "binary_expression")?;
 
-        Self {
-            left: Expression::new(node.c_by_n("left")),
-            op: node.c_by_n("operator").kind().to_string(),
-            right: Expression::new(node.c_by_n("right")),
-            context: Self::build_context(&node),
+        Ok(Self {
+            left: Expression::new(node.c_by_n("left")?)?,
+            op: node.c_by_n("operator")?.kind().to_string(),
+            right: Expression::new(node.c_by_n("right")?)?,
+            context: Self::build_context(&node)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -1151,23 +1288,26 @@ pub struct LocalVariableDeclaration {
 }
 
 impl LocalVariableDeclaration {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "local_variable_declaration");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "local_variable_declaration")?;
 
-        let modifiers = node.try_c_by_k("modifiers").map(|n| Modifiers::new(n));
+        let modifiers = node
+            .try_c_by_k("modifiers")
+            .map(Modifiers::new)
+            .transpose()?;
         let declarators = node
-            .cs_by_n("declarator")
+            .cs_by_n("declarator")?
             .into_iter()
-            .map(|n| VariableDeclarator::new(n))
-            .collect();
+            .map(VariableDeclarator::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             modifiers,
-            type_: UnannotatedType::new(node.c_by_n("type")),
+            type_: UnannotatedType::new(node.c_by_n("type")?)?,
             declarators,
             //is_parent_for_statement: node.parent().is_some_and(|n| n.kind() == "for_statement"),
             node_context: NodeContext::without_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -1207,23 +1347,29 @@ pub struct VariableDeclarator {
 }
 
 impl VariableDeclarator {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "variable_declarator");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "variable_declarator")?;
 
         let mut is_value_child_a_query_node = false;
-        let op = node.try_c_by_k("assignment_operator").map(ValueNode::new);
-        let value = node.try_c_by_n("value").map(|n| {
-            is_value_child_a_query_node = is_query_expression(&n);
-            VariableInitializer::Exp(Expression::new(n))
-        });
+        let op = node
+            .try_c_by_k("assignment_operator")
+            .map(ValueNode::new)
+            .transpose()?;
+        let value = node
+            .try_c_by_n("value")
+            .map(|n| {
+                is_value_child_a_query_node = is_query_expression(&n);
+                Ok(VariableInitializer::Exp(Expression::new(n)?))
+            })
+            .transpose()?;
 
-        Self {
-            name: ValueNode::new(node.c_by_n("name")),
+        Ok(Self {
+            name: ValueNode::new(node.c_by_n("name")?)?,
             op,
             value,
             is_value_child_a_query_node,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -1265,22 +1411,22 @@ pub struct GenericType {
 }
 
 impl GenericType {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "generic_type");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "generic_type")?;
 
         let generic_identifier = if let Some(t) = node.try_c_by_k("type_identifier") {
             GenericIdentifier::Type(t.value())
         } else if let Some(s) = node.try_c_by_k("scoped_type_identifier") {
-            GenericIdentifier::Scoped(ScopedTypeIdentifier::new(s))
+            GenericIdentifier::Scoped(ScopedTypeIdentifier::new(s)?)
         } else {
-            panic!("## can't build generic_identifier node in GenericType");
+            return Err(missing_child(&node, "type identifier"));
         };
 
-        Self {
+        Ok(Self {
             generic_identifier,
-            type_arguments: TypeArguments::new(node.c_by_k("type_arguments")),
+            type_arguments: TypeArguments::new(node.c_by_k("type_arguments")?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -1327,27 +1473,30 @@ pub struct IfStatement {
 }
 
 impl IfStatement {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "if_statement");
-
-        let alternative = node.try_c_by_n("alternative").map(|alt_stmt| {
-            let else_node = node
-                .children(&mut node.walk())
-                .find(|n| n.kind() == "else")
-                .expect("a mandatory `else` node is missing");
-
-            ElseClause {
-                else_node: ValueNode::new(else_node),
-                statement: Statement::new(alt_stmt),
-            }
-        });
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "if_statement")?;
+
+        let alternative = node
+            .try_c_by_n("alternative")
+            .map(|alt_stmt| {
+                let else_node = node
+                    .children(&mut node.walk())
+                    .find(|n| n.kind() == "else")
+                    .expect("a mandatory `else` node is missing");
+
+                Ok(ElseClause {
+                    else_node: ValueNode::new(else_node)?,
+                    statement: Statement::new(alt_stmt)?,
+                })
+            })
+            .transpose()?;
 
-        Self {
-            condition: ParenthesizedExpression::new(node.c_by_n("condition")),
-            consequence: Statement::new(node.c_by_n("consequence")),
+        Ok(Self {
+            condition: ParenthesizedExpression::new(node.c_by_n("condition")?)?,
+            consequence: Statement::new(node.c_by_n("consequence")?)?,
             alternative,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -1396,11 +1545,11 @@ pub struct ParenthesizedExpression {
 }
 
 impl ParenthesizedExpression {
-    pub fn new(node: Node) -> Self {
-        Self {
-            exp: Expression::new(node.first_c()),
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        Ok(Self {
+            exp: Expression::new(node.first_c()?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -1427,18 +1576,18 @@ pub enum ForInitOption {
 }
 
 impl ForInitOption {
-    pub fn new(node: Node) -> Self {
-        match node.kind() {
-            "local_variable_declaration" => Self::Declaration(LocalVariableDeclaration::new(node)),
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        Ok(match node.kind() {
+            "local_variable_declaration" => Self::Declaration(LocalVariableDeclaration::new(node)?),
             _ => Self::Exps(
                 node.parent()
                     .expect("node must have parent in ForInitOption")
-                    .cs_by_n("init")
+                    .cs_by_n("init")?
                     .into_iter()
-                    .map(|n| Expression::new(n))
-                    .collect(),
+                    .map(Expression::new)
+                    .collect::<Result<_, _>>()?,
             ),
-        }
+        })
     }
 }
 
@@ -1469,16 +1618,22 @@ pub struct ForStatement {
 }
 
 impl ForStatement {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "for_statement");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "for_statement")?;
 
         // when init or condition is None, the semicolon is immediate child of
         // ForStatement, for which case we need to collect and print.
         let mut punc_count = 0;
 
-        let init = node.try_c_by_n("init").map(|n| ForInitOption::new(n));
-        let condition = node.try_c_by_n("condition").map(|n| Expression::new(n));
-        let update = node.try_c_by_n("update").map(|n| Expression::new(n));
+        let init = node
+            .try_c_by_n("init")
+            .map(ForInitOption::new)
+            .transpose()?;
+        let condition = node
+            .try_c_by_n("condition")
+            .map(Expression::new)
+            .transpose()?;
+        let update = node.try_c_by_n("update").map(Expression::new).transpose()?;
 
         let semicolons = Self::build_semi_colons(&node);
 
@@ -1490,17 +1645,17 @@ impl ForStatement {
         }
 
         if punc_count > semicolons.len() {
-            panic!("## ForStatement missing semicolons.");
+            return Err(missing_child(&node, "semicolon"));
         }
 
-        Self {
+        Ok(Self {
             init,
             condition,
             update,
-            body: Statement::new(node.c_by_n("body")),
+            body: Statement::new(node.c_by_n("body")?)?,
             semicolons,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 
     fn build_semi_colons(node: &Node) -> Vec<Punctuation> {
@@ -1565,19 +1720,19 @@ pub struct EnhancedForStatement {
 }
 
 impl EnhancedForStatement {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "enhanced_for_statement");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "enhanced_for_statement")?;
 
-        //let modifiers = node.try_c_by_k("modifiers").map(|n| Modifiers::new(n));
+        //let modifiers = node.try_c_by_k("modifiers").map(Modifiers::new).transpose()?;
 
-        Self {
+        Ok(Self {
             //modifiers,
-            type_: UnannotatedType::new(node.c_by_n("type")),
-            name: ValueNode::new(node.c_by_n("name")),
-            value: Expression::new(node.c_by_n("value")),
-            body: Statement::new(node.c_by_n("body")),
+            type_: UnannotatedType::new(node.c_by_n("type")?)?,
+            name: ValueNode::new(node.c_by_n("name")?)?,
+            value: Expression::new(node.c_by_n("value")?)?,
+            body: Statement::new(node.c_by_n("body")?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -1614,23 +1769,23 @@ pub enum UpdateExpressionVariant {
 }
 
 impl UpdateExpressionVariant {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "update_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "update_expression")?;
 
-        let operator_node = node.c_by_n("operator");
-        let operand_node = node.c_by_n("operand");
+        let operator_node = node.c_by_n("operator")?;
+        let operand_node = node.c_by_n("operand")?;
 
-        if operator_node.start_byte() < operand_node.start_byte() {
+        Ok(if operator_node.start_byte() < operand_node.start_byte() {
             Self::Pre {
                 operator: operator_node.value(),
-                operand: Box::new(Expression::new(operand_node)),
+                operand: Box::new(Expression::new(operand_node)?),
             }
         } else {
             Self::Post {
-                operand: Box::new(Expression::new(operand_node)),
+                operand: Box::new(Expression::new(operand_node)?),
                 operator: operator_node.value(),
             }
-        }
+        })
     }
 }
 
@@ -1658,34 +1813,34 @@ pub struct ScopedTypeIdentifier {
 }
 
 impl ScopedTypeIdentifier {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "scoped_type_identifier");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "scoped_type_identifier")?;
 
-        let prefix_node = node.first_c();
+        let prefix_node = node.first_c()?;
         let scoped_choice = match prefix_node.kind() {
             "type_identifier" => ScopedChoice::TypeIdentifier(prefix_node.value()),
-            "scoped_type_identifier" => ScopedChoice::Scoped(Box::new(Self::new(prefix_node))),
-            "generic_type" => ScopedChoice::Generic(Box::new(GenericType::new(prefix_node))),
-            _ => panic_unknown_node(prefix_node, "ScopedTypeIdentifier"),
+            "scoped_type_identifier" => ScopedChoice::Scoped(Box::new(Self::new(prefix_node)?)),
+            "generic_type" => ScopedChoice::Generic(Box::new(GenericType::new(prefix_node)?)),
+            _ => return Err(unsupported_node(prefix_node, "ScopedTypeIdentifier")),
         };
 
         let annotations: Vec<_> = node
             .try_cs_by_k("annotation")
             .into_iter()
-            .map(|n| Annotation::new(n))
-            .collect();
+            .map(Annotation::new)
+            .collect::<Result<_, _>>()?;
 
         let type_identifier_node = node
-            .cs_by_k("type_identifier")
+            .cs_by_k("type_identifier")?
             .pop()
             .expect("## mandatory node type_identifier missing in ScopedTypeIdentifier");
 
-        Self {
+        Ok(Self {
             scoped_choice,
             annotations,
             type_identifier: type_identifier_node.value(),
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -1739,20 +1894,24 @@ pub struct ConstructorDeclaration {
 }
 
 impl ConstructorDeclaration {
-    pub fn new(node: Node) -> Self {
-        let modifiers = node.try_c_by_k("modifiers").map(|n| Modifiers::new(n));
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        let modifiers = node
+            .try_c_by_k("modifiers")
+            .map(Modifiers::new)
+            .transpose()?;
         let type_parameters = node
             .try_c_by_k("type_parameters")
-            .map(|n| TypeParameters::new(n));
+            .map(TypeParameters::new)
+            .transpose()?;
 
-        Self {
+        Ok(Self {
             modifiers,
             type_parameters,
-            name: ValueNode::new(node.c_by_n("name")),
-            parameters: FormalParameters::new(node.c_by_n("parameters")),
-            body: ConstructorBody::new(node.c_by_n("body")),
+            name: ValueNode::new(node.c_by_n("name")?)?,
+            parameters: FormalParameters::new(node.c_by_n("parameters")?)?,
+            body: ConstructorBody::new(node.c_by_n("body")?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -1782,30 +1941,29 @@ pub struct ConstructorBody {
 }
 
 impl ConstructorBody {
-    pub fn new(node: Node) -> Self {
-        let mut constructor_invocation = None;
-        let mut statements: Vec<BodyMember<Statement>> = Vec::new();
-
-        for (i, c) in node.children_vec().into_iter().enumerate() {
-            if i == 0 && c.kind() == "explicit_constructor_invocation" {
-                constructor_invocation = Some(BodyMember::new(&c, ConstructInvocation::new(c)));
-            } else {
-                statements.push(BodyMember::new(&c, Statement::new(c)));
-            }
-        }
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        let mut children = node.children_vec();
+        let constructor_invocation = match children.first() {
+            Some(c) if c.kind() == "explicit_constructor_invocation" => {
+                let c = children.remove(0);
+                Some(BodyMember::new(&c, ConstructInvocation::new(c)?))
+            }
+            _ => None,
+        };
+        let statements = BodyMember::collect(children, Statement::new, Statement::Verbatim)?;
 
-        Self {
+        Ok(Self {
             constructor_invocation,
             statements,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
 impl<'a> DocBuild<'a> for ConstructorBody {
     fn build_inner(&self, b: &'a DocBuilder<'a>, result: &mut Vec<DocRef<'a>>) {
         let bucket = get_comment_bucket(&self.node_context.id);
-        handle_pre_comments(b, bucket, result);
+        handle_pre_comments(b, &bucket, result);
 
         if bucket.dangling_comments.is_empty() {
             if self.constructor_invocation.is_none() && self.statements.is_empty() {
@@ -1832,7 +1990,7 @@ impl<'a> DocBuild<'a> for ConstructorBody {
             result.push(b.nl());
             result.push(b.txt("}"));
         } else {
-            handle_dangling_comments_in_bracket_surround(b, bucket, result);
+            handle_dangling_comments_in_bracket_surround(b, &bucket, result);
         }
     }
 }
@@ -1847,28 +2005,33 @@ pub struct ConstructInvocation {
 }
 
 impl ConstructInvocation {
-    pub fn new(node: Node) -> Self {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
         let object = node
             .try_c_by_n("object")
-            .map(|n| Box::new(PrimaryExpression::new(n)));
+            .map(|n| Ok(Box::new(PrimaryExpression::new(n)?)))
+            .transpose()?;
 
         let type_arguments = node
             .try_c_by_k("type_arguments")
-            .map(|n| TypeArguments::new(n));
-
-        let constructor = node.try_c_by_n("constructor").map(|n| match n.kind() {
-            "this" => Constructor::This,
-            "super" => Constructor::Super,
-            _ => panic_unknown_node(n, "Constructor"),
-        });
+            .map(TypeArguments::new)
+            .transpose()?;
+
+        let constructor = node
+            .try_c_by_n("constructor")
+            .map(|n| match n.kind() {
+                "this" => Ok(Constructor::This),
+                "super" => Ok(Constructor::Super),
+                _ => Err(unsupported_node(n, "Constructor")),
+            })
+            .transpose()?;
 
-        Self {
+        Ok(Self {
             object,
             type_arguments,
             constructor,
-            arguments: ArgumentList::new(node.c_by_n("arguments")),
+            arguments: ArgumentList::new(node.c_by_n("arguments")?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -1914,19 +2077,19 @@ pub struct TypeParameters {
 }
 
 impl TypeParameters {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "type_parameters");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "type_parameters")?;
 
         let type_parameters: Vec<_> = node
-            .cs_by_k("type_parameter")
+            .cs_by_k("type_parameter")?
             .into_iter()
-            .map(|n| TypeParameter::new(n))
-            .collect();
+            .map(TypeParameter::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             type_parameters,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -1952,18 +2115,18 @@ pub struct TypeParameter {
 }
 
 impl TypeParameter {
-    pub fn new(node: Node) -> Self {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
         let annotations: Vec<_> = node
             .try_cs_by_k("annotation")
             .into_iter()
-            .map(|n| Annotation::new(n))
-            .collect();
+            .map(Annotation::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             annotations,
-            type_identifier: node.cvalue_by_k("type_identifier"),
+            type_identifier: node.cvalue_by_k("type_identifier")?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -1991,21 +2154,25 @@ pub struct ObjectCreationExpression {
 }
 
 impl ObjectCreationExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "object_creation_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "object_creation_expression")?;
 
         let type_arguments = node
             .try_c_by_k("type_arguments")
-            .map(|n| TypeArguments::new(n));
-        let class_body = node.try_c_by_k("class_body").map(|n| ClassBody::new(n));
-
-        Self {
+            .map(TypeArguments::new)
+            .transpose()?;
+        let class_body = node
+            .try_c_by_k("class_body")
+            .map(ClassBody::new)
+            .transpose()?;
+
+        Ok(Self {
             type_arguments,
-            type_: UnannotatedType::new(node.c_by_n("type")),
-            arguments: ArgumentList::new(node.c_by_n("arguments")),
+            type_: UnannotatedType::new(node.c_by_n("type")?)?,
+            arguments: ArgumentList::new(node.c_by_n("arguments")?)?,
             class_body,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -2036,14 +2203,14 @@ pub struct RunAsStatement {
 }
 
 impl RunAsStatement {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "run_as_statement");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "run_as_statement")?;
 
-        Self {
-            user: ParenthesizedExpression::new(node.c_by_n("user")),
-            block: Block::new(node.c_by_k("block")),
+        Ok(Self {
+            user: ParenthesizedExpression::new(node.c_by_n("user")?)?,
+            block: Block::new(node.c_by_k("block")?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -2066,15 +2233,15 @@ pub struct DoStatement {
 }
 
 impl DoStatement {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "do_statement");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "do_statement")?;
 
-        Self {
-            body: Block::new(node.c_by_n("body")),
-            condition: ParenthesizedExpression::new(node.c_by_n("condition")),
+        Ok(Self {
+            body: Block::new(node.c_by_n("body")?)?,
+            condition: ParenthesizedExpression::new(node.c_by_n("condition")?)?,
 
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -2097,14 +2264,14 @@ pub struct WhileStatement {
 }
 
 impl WhileStatement {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "while_statement");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "while_statement")?;
 
-        Self {
-            condition: ParenthesizedExpression::new(node.c_by_n("condition")),
-            body: Statement::new(node.c_by_n("body")),
+        Ok(Self {
+            condition: ParenthesizedExpression::new(node.c_by_n("condition")?)?,
+            body: Statement::new(node.c_by_n("body")?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -2133,15 +2300,15 @@ pub struct UnaryExpression {
 }
 
 impl UnaryExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "unary_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "unary_expression")?;
 
-        let operator = node.cvalue_by_n("operator");
-        Self {
+        let operator = node.cvalue_by_n("operator")?;
+        Ok(Self {
             operator,
-            operand: Box::new(Expression::new(node.c_by_n("operand"))),
+            operand: Box::new(Expression::new(node.c_by_n("operand")?)?),
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -2164,33 +2331,33 @@ pub struct FieldAccess {
 }
 
 impl FieldAccess {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "field_access");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "field_access")?;
 
-        let obj_node = node.c_by_n("object");
+        let obj_node = node.c_by_n("object")?;
         let object = if obj_node.kind() == "super" {
-            MethodObject::Super(Super::new(obj_node))
+            MethodObject::Super(Super::new(obj_node)?)
         } else {
-            MethodObject::Primary(Box::new(PrimaryExpression::new(obj_node)))
+            MethodObject::Primary(Box::new(PrimaryExpression::new(obj_node)?))
         };
 
-        Self {
+        Ok(Self {
             object,
-            property_navigation: Self::get_property_navigation(&node),
-            field: FieldOption::new(node.c_by_n("field")),
+            property_navigation: Self::get_property_navigation(&node)?,
+            field: FieldOption::new(node.c_by_n("field")?)?,
             context: build_chaining_context(&node),
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 
-    fn get_property_navigation(parent_node: &Node) -> PropertyNavigation {
+    fn get_property_navigation(parent_node: &Node) -> Result<PropertyNavigation, FormatError> {
         let property_navigation =
             if let Some(n) = parent_node.try_c_by_k("safe_navigation_operator") {
-                PropertyNavigation::Safe(SafeNavigationOperator::new(n))
+                PropertyNavigation::Safe(SafeNavigationOperator::new(n)?)
             } else {
                 PropertyNavigation::Dot
             };
-        property_navigation
+        Ok(property_navigation)
     }
 }
 
@@ -2230,11 +2397,11 @@ pub enum FieldOption {
 }
 
 impl FieldOption {
-    pub fn new(node: Node) -> Self {
-        match node.kind() {
-            "this" => Self::This(This::new(node)),
-            _ => Self::Identifier(ValueNode::new(node)),
-        }
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        Ok(match node.kind() {
+            "this" => Self::This(This::new(node)?),
+            _ => Self::Identifier(ValueNode::new(node)?),
+        })
     }
 }
 
@@ -2261,17 +2428,23 @@ pub struct EnumDeclaration {
 }
 
 impl EnumDeclaration {
-    pub fn new(node: Node) -> Self {
-        let modifiers = node.try_c_by_k("modifiers").map(|n| Modifiers::new(n));
-        let interface = node.try_c_by_k("interfaces").map(|n| Interface::new(n));
-
-        Self {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        let modifiers = node
+            .try_c_by_k("modifiers")
+            .map(Modifiers::new)
+            .transpose()?;
+        let interface = node
+            .try_c_by_k("interfaces")
+            .map(Interface::new)
+            .transpose()?;
+
+        Ok(Self {
             modifiers,
-            name: ValueNode::new(node.c_by_n("name")),
+            name: ValueNode::new(node.c_by_n("name")?)?,
             interface,
-            body: EnumBody::new(node.c_by_n("body")),
+            body: EnumBody::new(node.c_by_n("body")?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -2295,31 +2468,64 @@ impl<'a> DocBuild<'a> for EnumDeclaration {
 
 #[derive(Debug)]
 pub struct EnumBody {
-    enum_constants: Vec<EnumConstant>,
+    enum_constants: Vec<EnumMember>,
     pub node_context: NodeContext,
 }
 
 impl EnumBody {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "enum_body");
-
-        let enum_constants = node
-            .try_cs_by_k("enum_constant")
-            .into_iter()
-            .map(|n| EnumConstant::new(n))
-            .collect();
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "enum_body")?;
+
+        let mut enum_constants = Vec::new();
+        for group in Self::group_errors(node.children_vec()) {
+            let (first, last) = (group[0], group[group.len() - 1]);
+            let member = if group.len() == 1 {
+                let verbatim = |v: Verbatim| EnumMember::Verbatim(v.with_punctuation(&first));
+                BodyMember::recover(first, EnumMember::new, verbatim)?.member
+            } else {
+                let context = FormatContext::current();
+                let reasons: Vec<_> = group
+                    .iter()
+                    .filter_map(|n| context.take_error_unit(n))
+                    .collect();
+                let reason = reasons.first().map_or("syntax error", |r| r.as_str());
+                EnumMember::Verbatim(
+                    Verbatim::spanning(first, last, reason).with_punctuation(&last),
+                )
+            };
+            enum_constants.push(member);
+        }
 
-        Self {
+        Ok(Self {
             enum_constants,
             node_context: NodeContext::with_punctuation(&node),
+        })
+    }
+
+    // Constants are separated by commas rather than lines, so in recovery mode an ERROR node is
+    // kept as written together with the constants on its line.
+    fn group_errors(children: Vec<Node>) -> Vec<Vec<Node>> {
+        let mut groups: Vec<Vec<Node>> = Vec::new();
+        for child in children {
+            if let Some(group) = groups.last_mut() {
+                let previous = group[group.len() - 1];
+                if (previous.is_error() || child.is_error())
+                    && previous.end_position().row == child.start_position().row
+                {
+                    group.push(child);
+                    continue;
+                }
+            }
+            groups.push(vec![child]);
         }
+        groups
     }
 }
 
 impl<'a> DocBuild<'a> for EnumBody {
     fn build_inner(&self, b: &'a DocBuilder<'a>, result: &mut Vec<DocRef<'a>>) {
         let bucket = get_comment_bucket(&self.node_context.id);
-        handle_pre_comments(b, bucket, result);
+        handle_pre_comments(b, &bucket, result);
 
         if bucket.dangling_comments.is_empty() {
             let docs = b.to_docs(&self.enum_constants);
@@ -2333,9 +2539,9 @@ impl<'a> DocBuild<'a> for EnumBody {
             let close = Insertable::new(Some(b.nl()), Some("}"), None);
             let doc = b.group_surround(&docs, sep, open, close);
             result.push(doc);
-            handle_post_comments(b, bucket, result);
+            handle_post_comments(b, &bucket, result);
         } else {
-            handle_dangling_comments_in_bracket_surround(b, bucket, result);
+            handle_dangling_comments_in_bracket_surround(b, &bucket, result);
         }
     }
 }
@@ -2348,15 +2554,18 @@ pub struct EnumConstant {
 }
 
 impl EnumConstant {
-    pub fn new(node: Node) -> Self {
-        let modifiers = node.try_c_by_k("modifiers").map(|n| Modifiers::new(n));
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        let modifiers = node
+            .try_c_by_k("modifiers")
+            .map(Modifiers::new)
+            .transpose()?;
 
-        Self {
+        Ok(Self {
             modifiers,
-            name: ValueNode::new(node.c_by_n("name")),
+            name: ValueNode::new(node.c_by_n("name")?)?,
 
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -2393,24 +2602,26 @@ pub enum DmlExpressionVariant {
 }
 
 impl DmlExpressionVariant {
-    pub fn new(node: Node) -> Self {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
         let security_mode = node
             .try_c_by_k("dml_security_mode")
-            .map(|n| DmlSecurityMode::new(n));
-        let target = Expression::new(node.c_by_n("target"));
+            .map(DmlSecurityMode::new)
+            .transpose()?;
+        let target = Expression::new(node.c_by_n("target")?)?;
 
-        let dml_type = DmlType::new(node.c_by_k("dml_type"));
-        match dml_type.variant {
+        let dml_type = DmlType::new(node.c_by_k("dml_type")?)?;
+        Ok(match dml_type.variant {
             DmlTypeVariant::Merge => Self::Merge {
                 dml_type,
                 security_mode,
                 target,
-                merge_with: Expression::new(node.c_by_n("merge_with")),
+                merge_with: Expression::new(node.c_by_n("merge_with")?)?,
             },
             DmlTypeVariant::Upsert => {
                 let unannotated = node
                     .try_c_by_n("upsert_key")
-                    .map(|n| Box::new(UnannotatedType::new(n)));
+                    .map(|n| Ok(Box::new(UnannotatedType::new(n)?)))
+                    .transpose()?;
                 Self::Upsert {
                     dml_type,
                     security_mode,
@@ -2423,7 +2634,7 @@ impl DmlExpressionVariant {
                 security_mode,
                 target,
             },
-        }
+        })
     }
 }
 
@@ -2497,13 +2708,13 @@ pub enum DmlSecurityMode {
 }
 
 impl DmlSecurityMode {
-    pub fn new(n: Node) -> Self {
-        let child = n.first_c();
-        match child.kind() {
+    pub fn new(n: Node) -> Result<Self, FormatError> {
+        let child = n.first_c()?;
+        Ok(match child.kind() {
             "user" => Self::User(child.value()),
             "system" => Self::System(child.value()),
-            _ => panic_unknown_node(n, "DmlSecurityMode"),
-        }
+            _ => return Err(unsupported_node(n, "DmlSecurityMode")),
+        })
     }
 }
 
@@ -2528,17 +2739,17 @@ pub enum DmlTypeVariant {
 }
 
 impl DmlTypeVariant {
-    pub fn new(node: Node) -> Self {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
         let k = node.kind();
-        match k {
+        Ok(match k {
             "insert" => Self::Insert,
             "update" => Self::Update,
             "delete" => Self::Delete,
             "undelete" => Self::Undelete,
             "merge" => Self::Merge,
             "upsert" => Self::Upsert,
-            _ => panic!("## unknown node: {} in DmlTypeVariant ", red(k)),
-        }
+            _ => return Err(unsupported_node(node, "DmlTypeVariant")),
+        })
     }
 }
 
@@ -2564,14 +2775,14 @@ pub struct ArrayAccess {
 }
 
 impl ArrayAccess {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "array_access");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "array_access")?;
 
-        Self {
-            array: PrimaryExpression::new(node.c_by_n("array")),
-            index: Expression::new(node.c_by_n("index")),
+        Ok(Self {
+            array: PrimaryExpression::new(node.c_by_n("array")?)?,
+            index: Expression::new(node.c_by_n("index")?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -2594,8 +2805,8 @@ pub struct ArrayCreationExpression {
 }
 
 impl ArrayCreationExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "array_creation_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "array_creation_expression")?;
 
         let value_node = node.try_c_by_n("value");
         let dimensions_node = node.try_c_by_n("dimensions");
@@ -2603,32 +2814,35 @@ impl ArrayCreationExpression {
         let variant = if value_node.is_none() {
             // DD
             let dimensions_exprs = node
-                .cs_by_k("dimensions_expr")
+                .cs_by_k("dimensions_expr")?
                 .into_iter()
-                .map(|n| DimensionsExpr::new(n))
-                .collect();
-            let dimensions = node.try_c_by_k("dimensions").map(|n| Dimensions::new(n));
+                .map(DimensionsExpr::new)
+                .collect::<Result<_, _>>()?;
+            let dimensions = node
+                .try_c_by_k("dimensions")
+                .map(Dimensions::new)
+                .transpose()?;
             ArrayCreationVariant::DD {
                 dimensions_exprs,
                 dimensions,
             }
         } else if dimensions_node.is_none() {
             //OnlyV
-            let value = ArrayInitializer::new(node.c_by_n("value"));
+            let value = ArrayInitializer::new(node.c_by_n("value")?)?;
             ArrayCreationVariant::OnlyV { value }
         } else {
             //DV
             ArrayCreationVariant::DV {
-                value: ArrayInitializer::new(value_node.unwrap()),
-                dimensions: Dimensions::new(dimensions_node.unwrap()),
+                value: ArrayInitializer::new(node.c_by_n("value")?)?,
+                dimensions: Dimensions::new(node.c_by_n("dimensions")?)?,
             }
         };
 
-        Self {
-            type_: SimpleType::new(node.c_by_n("type")),
+        Ok(Self {
+            type_: SimpleType::new(node.c_by_n("type")?)?,
             variant,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -2690,12 +2904,12 @@ pub struct Dimensions {
 }
 
 impl Dimensions {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "dimensions");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "dimensions")?;
 
-        Self {
+        Ok(Self {
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -2714,13 +2928,13 @@ pub struct DimensionsExpr {
 }
 
 impl DimensionsExpr {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "dimensions_expr");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "dimensions_expr")?;
 
-        Self {
-            exp: Expression::new(node.first_c()),
+        Ok(Self {
+            exp: Expression::new(node.first_c()?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -2741,16 +2955,16 @@ pub struct ReturnStatement {
 }
 
 impl ReturnStatement {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "return_statement");
-        let exp = node.try_first_c().map(|n| Expression::new(n));
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "return_statement")?;
+        let exp = node.try_first_c().map(Expression::new).transpose()?;
         let node_context = if exp.is_none() {
             NodeContext::with_inner_punctuation(&node)
         } else {
             NodeContext::with_punctuation(&node)
         };
 
-        Self { exp, node_context }
+        Ok(Self { exp, node_context })
     }
 }
 
@@ -2780,15 +2994,15 @@ pub struct TernaryExpression {
 }
 
 impl TernaryExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "ternary_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "ternary_expression")?;
 
-        Self {
-            condition: Expression::new(node.c_by_n("condition")),
-            consequence: Expression::new(node.c_by_n("consequence")),
-            alternative: Expression::new(node.c_by_n("alternative")),
+        Ok(Self {
+            condition: Expression::new(node.c_by_n("condition")?)?,
+            consequence: Expression::new(node.c_by_n("consequence")?)?,
+            alternative: Expression::new(node.c_by_n("alternative")?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -2817,30 +3031,30 @@ pub struct TryStatement {
 }
 
 impl TryStatement {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "try_statement");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "try_statement")?;
 
         let tail = if node.try_c_by_k("finally_clause").is_some() {
             TryStatementTail::CatchesFinally(
                 node.try_cs_by_k("catch_clause")
                     .into_iter()
-                    .map(|n| CatchClause::new(n))
-                    .collect(),
-                FinallyClause::new(node.c_by_k("finally_clause")),
+                    .map(CatchClause::new)
+                    .collect::<Result<_, _>>()?,
+                FinallyClause::new(node.c_by_k("finally_clause")?)?,
             )
         } else {
             TryStatementTail::Catches(
-                node.cs_by_k("catch_clause")
+                node.cs_by_k("catch_clause")?
                     .into_iter()
-                    .map(|n| CatchClause::new(n))
-                    .collect(),
+                    .map(CatchClause::new)
+                    .collect::<Result<_, _>>()?,
             )
         };
-        Self {
-            body: Block::new(node.c_by_n("body")),
+        Ok(Self {
+            body: Block::new(node.c_by_n("body")?)?,
             tail,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -2886,14 +3100,14 @@ pub struct CatchClause {
 }
 
 impl CatchClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "catch_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "catch_clause")?;
 
-        Self {
-            formal_parameter: FormalParameter::new(node.c_by_k("formal_parameter")),
-            body: Block::new(node.c_by_n("body")),
+        Ok(Self {
+            formal_parameter: FormalParameter::new(node.c_by_k("formal_parameter")?)?,
+            body: Block::new(node.c_by_n("body")?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -2917,13 +3131,13 @@ pub struct FinallyClause {
 }
 
 impl FinallyClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "finally_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "finally_clause")?;
 
-        Self {
-            body: Block::new(node.c_by_k("block")),
+        Ok(Self {
+            body: Block::new(node.c_by_k("block")?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -2943,11 +3157,11 @@ pub struct StaticInitializer {
 }
 
 impl StaticInitializer {
-    pub fn new(node: Node) -> Self {
-        Self {
-            block: Block::new(node.c_by_k("block")),
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        Ok(Self {
+            block: Block::new(node.c_by_k("block")?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -2971,25 +3185,30 @@ pub struct InterfaceDeclaration {
 }
 
 impl InterfaceDeclaration {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "interface_declaration");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "interface_declaration")?;
 
-        let modifiers = node.try_c_by_k("modifiers").map(|n| Modifiers::new(n));
+        let modifiers = node
+            .try_c_by_k("modifiers")
+            .map(Modifiers::new)
+            .transpose()?;
         let type_parameters = node
             .try_c_by_k("type_parameters")
-            .map(|n| TypeParameters::new(n));
+            .map(TypeParameters::new)
+            .transpose()?;
         let extends = node
             .try_c_by_k("extends_interfaces")
-            .map(|n| ExtendsInterface::new(n));
+            .map(ExtendsInterface::new)
+            .transpose()?;
 
-        Self {
+        Ok(Self {
             modifiers,
-            name: ValueNode::new(node.c_by_n("name")),
+            name: ValueNode::new(node.c_by_n("name")?)?,
             type_parameters,
             extends,
-            body: InterfaceBody::new(node.c_by_n("body")),
+            body: InterfaceBody::new(node.c_by_n("body")?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3023,11 +3242,11 @@ pub struct ExtendsInterface {
 }
 
 impl ExtendsInterface {
-    pub fn new(node: Node) -> Self {
-        Self {
-            type_list: TypeList::new(node.c_by_k("type_list")),
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        Ok(Self {
+            type_list: TypeList::new(node.c_by_k("type_list")?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3048,47 +3267,32 @@ pub struct InterfaceBody {
 }
 
 impl InterfaceBody {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "interface_body");
-
-        let members: Vec<_> = node
-            .children_vec()
-            .into_iter()
-            .map(|n| {
-                let member = match n.kind() {
-                    "constant_declaration" => {
-                        InterfaceMember::Constant(ConstantDeclaration::new(n))
-                    }
-                    "enum_declaration" => InterfaceMember::EnumD(EnumDeclaration::new(n)),
-                    "method_declaration" => InterfaceMember::Method(MethodDeclaration::new(n)),
-                    "class_declaration" => InterfaceMember::Class(ClassDeclaration::new(n)),
-                    "interface_declaration" => {
-                        InterfaceMember::Interface(InterfaceDeclaration::new(n))
-                    }
-                    _ => panic_unknown_node(n, "InterfaceBody"),
-                };
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "interface_body")?;
 
-                BodyMember::new(&n, member)
-            })
-            .collect();
+        let members: Vec<_> = BodyMember::collect(
+            node.children_vec(),
+            InterfaceMember::new,
+            InterfaceMember::Verbatim,
+        )?;
 
-        Self {
+        Ok(Self {
             members,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
 impl<'a> DocBuild<'a> for InterfaceBody {
     fn build_inner(&self, b: &'a DocBuilder<'a>, result: &mut Vec<DocRef<'a>>) {
         let bucket = get_comment_bucket(&self.node_context.id);
-        handle_pre_comments(b, bucket, result);
+        handle_pre_comments(b, &bucket, result);
 
         if bucket.dangling_comments.is_empty() {
             result.push(b.surround_body_members(&self.members, "{", "}"));
-            handle_post_comments(b, bucket, result);
+            handle_post_comments(b, &bucket, result);
         } else {
-            handle_dangling_comments_in_bracket_surround(b, bucket, result);
+            handle_dangling_comments_in_bracket_surround(b, &bucket, result);
         }
     }
 }
@@ -3100,9 +3304,23 @@ pub enum InterfaceMember {
     Method(MethodDeclaration),
     Class(ClassDeclaration),
     Interface(InterfaceDeclaration),
+    Verbatim(Verbatim),
     //Semicolon,
 }
 
+impl InterfaceMember {
+    pub fn new(n: Node) -> Result<Self, FormatError> {
+        Ok(match n.kind() {
+            "constant_declaration" => Self::Constant(ConstantDeclaration::new(n)?),
+            "enum_declaration" => Self::EnumD(EnumDeclaration::new(n)?),
+            "method_declaration" => Self::Method(MethodDeclaration::new(n)?),
+            "class_declaration" => Self::Class(ClassDeclaration::new(n)?),
+            "interface_declaration" => Self::Interface(InterfaceDeclaration::new(n)?),
+            _ => return Err(unsupported_node(n, "InterfaceBody")),
+        })
+    }
+}
+
 impl<'a> DocBuild<'a> for InterfaceMember {
     fn build_inner(&self, b: &'a DocBuilder<'a>, result: &mut Vec<DocRef<'a>>) {
         match self {
@@ -3121,6 +3339,9 @@ impl<'a> DocBuild<'a> for InterfaceMember {
             Self::Interface(n) => {
                 result.push(n.build(b));
             }
+            Self::Verbatim(n) => {
+                result.push(n.build(b));
+            }
         }
     }
 }
@@ -3134,20 +3355,23 @@ pub struct ConstantDeclaration {
 }
 
 impl ConstantDeclaration {
-    pub fn new(node: Node) -> Self {
-        let modifiers = node.try_c_by_k("modifiers").map(|n| Modifiers::new(n));
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        let modifiers = node
+            .try_c_by_k("modifiers")
+            .map(Modifiers::new)
+            .transpose()?;
         let declarators = node
-            .cs_by_n("declarator")
+            .cs_by_n("declarator")?
             .into_iter()
-            .map(|n| VariableDeclarator::new(n))
-            .collect();
+            .map(VariableDeclarator::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             modifiers,
-            type_: UnannotatedType::new(node.c_by_n("type")),
+            type_: UnannotatedType::new(node.c_by_n("type")?)?,
             declarators,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3177,21 +3401,21 @@ pub struct AccessorList {
 }
 
 impl AccessorList {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "accessor_list");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "accessor_list")?;
 
         let accessor_declarations: Vec<_> = node
-            .cs_by_k("accessor_declaration")
+            .cs_by_k("accessor_declaration")?
             .into_iter()
-            .map(|n| AccessorDeclaration::new(n))
-            .collect();
+            .map(AccessorDeclaration::new)
+            .collect::<Result<_, _>>()?;
         let child_has_body_section = accessor_declarations.iter().any(|n| n.body.is_some());
 
-        Self {
+        Ok(Self {
             accessor_declarations,
             child_has_body_section,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3233,15 +3457,18 @@ pub struct AccessorDeclaration {
 }
 
 impl AccessorDeclaration {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "accessor_declaration");
-
-        Self {
-            modifiers: node.try_c_by_k("modifiers").map(|n| Modifiers::new(n)),
-            accessor: node.cvalue_by_n("accessor"),
-            body: node.try_c_by_n("body").map(|n| Block::new(n)),
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "accessor_declaration")?;
+
+        Ok(Self {
+            modifiers: node
+                .try_c_by_k("modifiers")
+                .map(Modifiers::new)
+                .transpose()?,
+            accessor: node.cvalue_by_n("accessor")?,
+            body: node.try_c_by_n("body").map(Block::new).transpose()?,
             node_context: NodeContext::with_inner_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3269,14 +3496,14 @@ pub struct CastExpression {
 }
 
 impl CastExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "cast_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "cast_expression")?;
 
-        Self {
-            type_: Type::new(node.c_by_n("type")),
-            value: Expression::new(node.c_by_n("value")),
+        Ok(Self {
+            type_: Type::new(node.c_by_n("type")?)?,
+            value: Expression::new(node.c_by_n("value")?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3298,13 +3525,13 @@ pub struct ThrowStatement {
 }
 
 impl ThrowStatement {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "throw_statement");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "throw_statement")?;
 
-        Self {
-            exp: Expression::new(node.first_c()),
+        Ok(Self {
+            exp: Expression::new(node.first_c()?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3324,20 +3551,23 @@ pub struct BreakStatement {
 }
 
 impl BreakStatement {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "break_statement");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "break_statement")?;
 
-        let identifier = node.try_c_by_k("identifier").map(|n| ValueNode::new(n));
+        let identifier = node
+            .try_c_by_k("identifier")
+            .map(ValueNode::new)
+            .transpose()?;
         let node_context = if identifier.is_none() {
             NodeContext::with_inner_punctuation(&node)
         } else {
             NodeContext::with_punctuation(&node)
         };
 
-        Self {
+        Ok(Self {
             identifier,
             node_context,
-        }
+        })
     }
 }
 
@@ -3365,20 +3595,23 @@ pub struct ContinueStatement {
 }
 
 impl ContinueStatement {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "continue_statement");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "continue_statement")?;
 
-        let identifier = node.try_c_by_k("identifier").map(|n| ValueNode::new(n));
+        let identifier = node
+            .try_c_by_k("identifier")
+            .map(ValueNode::new)
+            .transpose()?;
         let node_context = if identifier.is_none() {
             NodeContext::with_inner_punctuation(&node)
         } else {
             NodeContext::with_punctuation(&node)
         };
 
-        Self {
+        Ok(Self {
             identifier,
             node_context,
-        }
+        })
     }
 }
 
@@ -3407,14 +3640,14 @@ pub struct SwitchExpression {
 }
 
 impl SwitchExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "switch_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "switch_expression")?;
 
-        Self {
-            condition: Expression::new(node.c_by_n("condition")),
-            body: SwitchBlock::new(node.c_by_n("body")),
+        Ok(Self {
+            condition: Expression::new(node.c_by_n("condition")?)?,
+            body: SwitchBlock::new(node.c_by_n("body")?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3437,19 +3670,19 @@ pub struct SwitchBlock {
 }
 
 impl SwitchBlock {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "switch_block");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "switch_block")?;
 
         let rules = node
-            .cs_by_k("switch_rule")
+            .cs_by_k("switch_rule")?
             .into_iter()
-            .map(|n| SwitchRule::new(n))
-            .collect();
+            .map(SwitchRule::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             rules,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3475,14 +3708,14 @@ pub struct SwitchRule {
 }
 
 impl SwitchRule {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "switch_rule");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "switch_rule")?;
 
-        Self {
-            label: SwitchLabel::new(node.c_by_k("switch_label")),
-            block: Block::new(node.c_by_k("block")),
+        Ok(Self {
+            label: SwitchLabel::new(node.c_by_k("switch_label")?)?,
+            block: Block::new(node.c_by_k("block")?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3504,21 +3737,21 @@ pub enum SwitchLabel {
 }
 
 impl SwitchLabel {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "switch_label");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "switch_label")?;
 
-        if node.children_vec().is_empty() {
+        Ok(if node.children_vec().is_empty() {
             Self::Else
         } else if let Some(when_node) = node.try_c_by_k("when_sobject_type") {
-            Self::WhenSObject(WhenSObjectType::new(when_node))
+            Self::WhenSObject(WhenSObjectType::new(when_node)?)
         } else {
             let expressions = node
                 .children_vec()
                 .into_iter()
                 .map(Expression::new)
-                .collect();
+                .collect::<Result<_, _>>()?;
             Self::Expressions(expressions)
-        }
+        })
     }
 }
 
@@ -3550,7 +3783,7 @@ pub struct WhenSObjectType {
 }
 
 impl WhenSObjectType {
-    pub fn new(node: Node) -> Self {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
         let mut unannotated_type = None;
         let mut identifier = None;
 
@@ -3560,17 +3793,17 @@ impl WhenSObjectType {
                     identifier = Some(child.value());
                 }
                 _ => {
-                    unannotated_type = Some(UnannotatedType::new(child));
+                    unannotated_type = Some(UnannotatedType::new(child)?);
                 }
             }
         }
 
-        Self {
+        Ok(Self {
             unannotated_type: unannotated_type
                 .expect("Missing unannotated_type in WhenSObjectType"),
             identifier: identifier.expect("Missing identifier in WhenSObjectType"),
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3592,14 +3825,14 @@ pub struct InstanceOfExpression {
 }
 
 impl InstanceOfExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "instanceof_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "instanceof_expression")?;
 
-        Self {
-            left: Expression::new(node.c_by_n("left")),
-            right: Type::new(node.c_by_n("right")),
+        Ok(Self {
+            left: Expression::new(node.c_by_n("left")?)?,
+            right: Type::new(node.c_by_n("right")?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3620,14 +3853,17 @@ pub struct VersionExpression {
 }
 
 impl VersionExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "version_expression");
-
-        let version_number = node.try_c_by_n("version_num").map(|n| ValueNode::new(n));
-        Self {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "version_expression")?;
+
+        let version_number = node
+            .try_c_by_n("version_num")
+            .map(ValueNode::new)
+            .transpose()?;
+        Ok(Self {
             version_number,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3651,13 +3887,13 @@ pub struct JavaFieldAccess {
 }
 
 impl JavaFieldAccess {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "java_field_access");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "java_field_access")?;
 
-        Self {
-            field_access: FieldAccess::new(node.c_by_k("field_access")),
+        Ok(Self {
+            field_access: FieldAccess::new(node.c_by_k("field_access")?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3677,14 +3913,14 @@ pub struct JavaType {
 }
 
 impl JavaType {
-    pub fn new(node: Node) -> Self {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
         let scoped_type_identifier =
-            ScopedTypeIdentifier::new(node.c_by_k("scoped_type_identifier"));
+            ScopedTypeIdentifier::new(node.c_by_k("scoped_type_identifier")?)?;
 
-        Self {
+        Ok(Self {
             scoped_type_identifier,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3705,14 +3941,14 @@ pub struct ArrayType {
 }
 
 impl ArrayType {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "array_type");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "array_type")?;
 
-        Self {
-            element: UnannotatedType::new(node.c_by_n("element")),
-            dimensions: Dimensions::new(node.c_by_n("dimensions")),
+        Ok(Self {
+            element: UnannotatedType::new(node.c_by_n("element")?)?,
+            dimensions: Dimensions::new(node.c_by_n("dimensions")?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3735,22 +3971,22 @@ pub struct TriggerDeclaration {
 }
 
 impl TriggerDeclaration {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "trigger_declaration");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "trigger_declaration")?;
 
         let events = node
-            .cs_by_k("trigger_event")
+            .cs_by_k("trigger_event")?
             .into_iter()
-            .map(|n| TriggerEvent::new(n))
-            .collect();
+            .map(TriggerEvent::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
-            name: ValueNode::new(node.c_by_n("name")),
-            object: ValueNode::new(node.c_by_n("object")),
+        Ok(Self {
+            name: ValueNode::new(node.c_by_n("name")?)?,
+            object: ValueNode::new(node.c_by_n("object")?)?,
             events,
-            body: TriggerBody::new(node.c_by_n("body")),
+            body: TriggerBody::new(node.c_by_n("body")?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3782,13 +4018,13 @@ pub struct TriggerEvent {
 }
 
 impl TriggerEvent {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "trigger_event");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "trigger_event")?;
 
-        Self {
-            event: TriggerEventVariant::new(node.first_c()),
+        Ok(Self {
+            event: TriggerEventVariant::new(node.first_c()?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3802,18 +4038,19 @@ impl<'a> DocBuild<'a> for TriggerEvent {
 
 #[derive(Debug)]
 pub struct TriggerBody {
-    pub block: Block,
+    pub block: Statement,
     pub node_context: NodeContext,
 }
 
 impl TriggerBody {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "trigger_body");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "trigger_body")?;
 
-        Self {
-            block: Block::new(node.c_by_k("block")),
+        let block = node.c_by_k("block")?;
+        Ok(Self {
+            block: BodyMember::recover(block, Statement::new, Statement::Verbatim)?.member,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3833,20 +4070,20 @@ pub struct QueryExpression {
 }
 
 impl QueryExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "query_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "query_expression")?;
 
         let query_body = if let Some(soql_node) = node.try_c_by_k("soql_query_body") {
-            QueryBody::Soql(SoqlQueryBody::new(soql_node))
+            QueryBody::Soql(SoqlQueryBody::new(soql_node)?)
         } else {
-            QueryBody::Sosl(SoslQueryBody::new(node.c_by_k("sosl_query_body")))
+            QueryBody::Sosl(SoslQueryBody::new(node.c_by_k("sosl_query_body")?)?)
         };
 
-        Self {
+        Ok(Self {
             query_body,
             context: build_chaining_context(&node),
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3912,28 +4149,37 @@ pub struct SoslQueryBody {
 }
 
 impl SoslQueryBody {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "sosl_query_body");
-
-        let find_clause = FindClause::new(node.c_by_k("find_clause"));
-        let in_clause = node.try_c_by_k("in_clause").map(|n| InClause::new(n));
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "sosl_query_body")?;
+
+        let find_clause = FindClause::new(node.c_by_k("find_clause")?)?;
+        let in_clause = node
+            .try_c_by_k("in_clause")
+            .map(InClause::new)
+            .transpose()?;
         let returning_clause = node
             .try_c_by_k("returning_clause")
-            .map(|n| ReturningClause::new(n));
+            .map(ReturningClause::new)
+            .transpose()?;
         let with_clauses = node
             .try_cs_by_k("with_clause")
             .into_iter()
-            .map(|n| SoslWithClause::new(n))
-            .collect();
+            .map(SoslWithClause::new)
+            .collect::<Result<_, _>>()?;
         let using_clause = node
             .try_c_by_k("sosl_using_clause")
-            .map(|n| SoslUsingClause::new(n));
-        let limit_clause = node.try_c_by_k("limit_clause").map(|n| LimitClause::new(n));
+            .map(SoslUsingClause::new)
+            .transpose()?;
+        let limit_clause = node
+            .try_c_by_k("limit_clause")
+            .map(LimitClause::new)
+            .transpose()?;
         let update_clause = node
             .try_c_by_k("update_clause")
-            .map(|n| UpdateClause::new(n));
+            .map(UpdateClause::new)
+            .transpose()?;
 
-        Self {
+        Ok(Self {
             find_clause,
             in_clause,
             returning_clause,
@@ -3942,7 +4188,7 @@ impl SoslQueryBody {
             limit_clause,
             update_clause,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -3988,14 +4234,16 @@ pub enum FindClause {
 }
 
 impl FindClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "find_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "find_clause")?;
 
-        if let Some(bound_node) = node.try_c_by_k("bound_apex_expression") {
-            Self::Bound(BoundApexExpression::new(bound_node))
-        } else {
-            Self::Term(node.cvalue_by_k("term"))
-        }
+        Ok(
+            if let Some(bound_node) = node.try_c_by_k("bound_apex_expression") {
+                Self::Bound(BoundApexExpression::new(bound_node)?)
+            } else {
+                Self::Term(node.cvalue_by_k("term")?)
+            },
+        )
     }
 }
 
@@ -4020,13 +4268,13 @@ pub struct InClause {
 }
 
 impl InClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "in_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "in_clause")?;
 
-        Self {
-            in_type: ValueNode::new(node.c_by_k("in_type")),
+        Ok(Self {
+            in_type: ValueNode::new(node.c_by_k("in_type")?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4048,17 +4296,17 @@ pub struct ReturningClause {
 }
 
 impl ReturningClause {
-    pub fn new(node: Node) -> Self {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
         let sobject_returns = node
-            .cs_by_k("sobject_return")
+            .cs_by_k("sobject_return")?
             .into_iter()
-            .map(|n| SObjectReturn::new(n))
-            .collect();
+            .map(SObjectReturn::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             sobject_returns,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4085,34 +4333,48 @@ pub struct SObjectReturn {
 }
 
 impl SObjectReturn {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "sobject_return");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "sobject_return")?;
 
         let sobject_return_query = node
             .try_c_by_k("selected_fields")
-            .map(|n| SObjectReturnQuery {
-                selected_fields: n
-                    .children_vec()
-                    .into_iter()
-                    .map(|selectable_node| SelectableExpression::new(selectable_node))
-                    .collect(),
-                using_clause: node.try_c_by_k("using_clause").map(|n| UsingClause::new(n)),
-                where_clause: node.try_c_by_k("where_clause").map(|n| WhereClause::new(n)),
-                order_by_clause: node
-                    .try_c_by_k("order_by_clause")
-                    .map(|n| OrderByClause::new(n)),
-                limit_clause: node.try_c_by_k("limit_clause").map(|n| LimitClause::new(n)),
-                offset_clause: node
-                    .try_c_by_k("offset_clause")
-                    .map(|n| OffsetClause::new(n)),
-                node_context: NodeContext::with_punctuation(&n),
-            });
+            .map(|n| {
+                Ok(SObjectReturnQuery {
+                    selected_fields: n
+                        .children_vec()
+                        .into_iter()
+                        .map(SelectableExpression::new)
+                        .collect::<Result<_, _>>()?,
+                    using_clause: node
+                        .try_c_by_k("using_clause")
+                        .map(UsingClause::new)
+                        .transpose()?,
+                    where_clause: node
+                        .try_c_by_k("where_clause")
+                        .map(WhereClause::new)
+                        .transpose()?,
+                    order_by_clause: node
+                        .try_c_by_k("order_by_clause")
+                        .map(OrderByClause::new)
+                        .transpose()?,
+                    limit_clause: node
+                        .try_c_by_k("limit_clause")
+                        .map(LimitClause::new)
+                        .transpose()?,
+                    offset_clause: node
+                        .try_c_by_k("offset_clause")
+                        .map(OffsetClause::new)
+                        .transpose()?,
+                    node_context: NodeContext::with_punctuation(&n),
+                })
+            })
+            .transpose()?;
 
-        Self {
-            identifier: ValueNode::new(node.c_by_k("identifier")),
+        Ok(Self {
+            identifier: ValueNode::new(node.c_by_k("identifier")?)?,
             sobject_return_query,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4192,35 +4454,46 @@ pub struct SoqlQueryBody {
 }
 
 impl SoqlQueryBody {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "soql_query_body");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "soql_query_body")?;
 
-        let where_clause = node.try_c_by_n("where_clause").map(|n| WhereClause::new(n));
+        let where_clause = node
+            .try_c_by_n("where_clause")
+            .map(WhereClause::new)
+            .transpose()?;
         let with_clause = node
             .try_c_by_n("with_clause")
-            .map(|n| SoqlWithClause::new(n));
+            .map(SoqlWithClause::new)
+            .transpose()?;
         let group_by_clause = node
             .try_c_by_n("group_by_clause")
-            .map(|n| GroupByClause::new(n));
+            .map(GroupByClause::new)
+            .transpose()?;
         let order_by_clause = node
             .try_c_by_n("order_by_clause")
-            .map(|n| OrderByClause::new(n));
-        let limit_clause = node.try_c_by_n("limit_clause").map(|n| LimitClause::new(n));
+            .map(OrderByClause::new)
+            .transpose()?;
+        let limit_clause = node
+            .try_c_by_n("limit_clause")
+            .map(LimitClause::new)
+            .transpose()?;
         let offset_clause = node
             .try_c_by_n("offset_clause")
-            .map(|n| OffsetClause::new(n));
+            .map(OffsetClause::new)
+            .transpose()?;
         let all_rows_clause = node
             .try_c_by_n("all_rows_clause")
-            .map(|n| AllRowsClause::new(n));
+            .map(AllRowsClause::new)
+            .transpose()?;
         let for_clause = node
             .try_cs_by_k("for_clause")
             .into_iter()
-            .map(|n| ForClause::new(n))
-            .collect();
+            .map(ForClause::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
-            select_clause: SelectClause::new(node.c_by_n("select_clause")),
-            from_clause: FromClause::new(node.c_by_n("from_clause")),
+        Ok(Self {
+            select_clause: SelectClause::new(node.c_by_n("select_clause")?)?,
+            from_clause: FromClause::new(node.c_by_n("from_clause")?)?,
             where_clause,
             with_clause,
             group_by_clause,
@@ -4230,7 +4503,7 @@ impl SoqlQueryBody {
             for_clause,
             all_rows_clause,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4286,13 +4559,13 @@ pub struct FromClause {
 }
 
 impl FromClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "from_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "from_clause")?;
 
-        Self {
-            content: StorageVariant::new(node.first_c()),
+        Ok(Self {
+            content: StorageVariant::new(node.first_c()?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4313,14 +4586,14 @@ pub struct StorageAlias {
 }
 
 impl StorageAlias {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "storage_alias");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "storage_alias")?;
 
-        Self {
-            storage_identifier: StorageIdentifier::new(node.c_by_k("storage_identifier")),
-            identifier: ValueNode::new(node.c_by_k("identifier")),
+        Ok(Self {
+            storage_identifier: StorageIdentifier::new(node.c_by_k("storage_identifier")?)?,
+            identifier: ValueNode::new(node.c_by_k("identifier")?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4341,13 +4614,13 @@ pub struct LimitClause {
 }
 
 impl LimitClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "limit_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "limit_clause")?;
 
-        Self {
-            limit_value: LimitValue::new(node.first_c()),
+        Ok(Self {
+            limit_value: LimitValue::new(node.first_c()?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4367,17 +4640,17 @@ pub struct UpdateClause {
 }
 
 impl UpdateClause {
-    pub fn new(node: Node) -> Self {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
         let update_types = node
-            .cs_by_k("update_type")
+            .cs_by_k("update_type")?
             .into_iter()
-            .map(|n| ValueNode::new(n))
-            .collect();
+            .map(ValueNode::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             update_types,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4401,13 +4674,13 @@ pub struct BoundApexExpression {
 }
 
 impl BoundApexExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "bound_apex_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "bound_apex_expression")?;
 
-        Self {
-            exp: Box::new(Expression::new(node.first_c())),
+        Ok(Self {
+            exp: Box::new(Expression::new(node.first_c()?)?),
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4427,13 +4700,13 @@ pub struct SoslUsingClause {
 }
 
 impl SoslUsingClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "sosl_using_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "sosl_using_clause")?;
 
-        Self {
-            search: UsingSearch::new(node.first_c()),
+        Ok(Self {
+            search: UsingSearch::new(node.first_c()?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4453,12 +4726,12 @@ pub enum UsingSearch {
 }
 
 impl UsingSearch {
-    pub fn new(node: Node) -> Self {
-        match node.kind() {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        Ok(match node.kind() {
             "using_phrase_search" => Self::Phrase,
             "using_advanced_search" => Self::Advanced,
-            _ => panic_unknown_node(node, "UsingSearch"),
-        }
+            _ => return Err(unsupported_node(node, "UsingSearch")),
+        })
     }
 }
 
@@ -4482,13 +4755,13 @@ pub struct UsingClause {
 }
 
 impl UsingClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "using_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "using_clause")?;
 
-        Self {
-            option: UsingClauseOption::new(node.first_c()),
+        Ok(Self {
+            option: UsingClauseOption::new(node.first_c()?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4509,13 +4782,13 @@ pub enum UsingClauseOption {
 }
 
 impl UsingClauseOption {
-    pub fn new(node: Node) -> Self {
-        match node.kind() {
-            "using_scope_clause" => Self::Scope(UsingScopeClause::new(node)),
-            "using_lookup_clause" => Self::Lookup(UsingLookupClause::new(node)),
-            "using_listview_clause" => Self::Listview(UsingListviewClause::new(node)),
-            _ => panic_unknown_node(node, "UsingClauseOption"),
-        }
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        Ok(match node.kind() {
+            "using_scope_clause" => Self::Scope(UsingScopeClause::new(node)?),
+            "using_lookup_clause" => Self::Lookup(UsingLookupClause::new(node)?),
+            "using_listview_clause" => Self::Listview(UsingListviewClause::new(node)?),
+            _ => return Err(unsupported_node(node, "UsingClauseOption")),
+        })
     }
 }
 
@@ -4542,13 +4815,13 @@ pub struct UsingScopeClause {
 }
 
 impl UsingScopeClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "using_scope_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "using_scope_clause")?;
 
-        Self {
-            type_: ValueNode::new(node.c_by_k("using_scope_type")),
+        Ok(Self {
+            type_: ValueNode::new(node.c_by_k("using_scope_type")?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4569,19 +4842,20 @@ pub struct UsingLookupClause {
 }
 
 impl UsingLookupClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "using_lookup_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "using_lookup_clause")?;
 
-        let lookup_field = DottedIdentifier::new(node.c_by_n("using_lookup_clause"));
+        let lookup_field = DottedIdentifier::new(node.c_by_n("using_lookup_clause")?)?;
         let bind_clause = node
             .try_c_by_k("using_lookup_bind_clause")
-            .map(|n| UsingLookupBindClause::new(n));
+            .map(UsingLookupBindClause::new)
+            .transpose()?;
 
-        Self {
+        Ok(Self {
             lookup_field,
             bind_clause,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4605,13 +4879,13 @@ pub struct UsingListviewClause {
 }
 
 impl UsingListviewClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "using_listview_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "using_listview_clause")?;
 
-        Self {
-            identifier: ValueNode::new(node.c_by_k("identifier")),
+        Ok(Self {
+            identifier: ValueNode::new(node.c_by_k("identifier")?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4631,20 +4905,20 @@ pub struct UsingLookupBindClause {
 }
 
 impl UsingLookupBindClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "using_lookup_bind_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "using_lookup_bind_clause")?;
 
         let bind_exps = node
             .try_cs_by_k("using_lookup_bind_expression")
             .into_iter()
-            .map(|n| UsingLookupBindExpression::new(n))
-            .collect();
+            .map(UsingLookupBindExpression::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             bind_exps,
 
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4669,14 +4943,14 @@ pub struct UsingLookupBindExpression {
 }
 
 impl UsingLookupBindExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "using_lookup_bind_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "using_lookup_bind_expression")?;
 
-        Self {
-            field: ValueNode::new(node.c_by_k("field")),
-            bound_value: SoqlLiteral::new(node.c_by_n("bound_value")),
+        Ok(Self {
+            field: ValueNode::new(node.c_by_k("field")?)?,
+            bound_value: SoqlLiteral::new(node.c_by_n("bound_value")?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4697,13 +4971,13 @@ pub struct WhereClause {
 }
 
 impl WhereClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "where_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "where_clause")?;
 
-        Self {
-            boolean_exp: BooleanExpression::new(node.first_c()),
+        Ok(Self {
+            boolean_exp: BooleanExpression::new(node.first_c()?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4728,14 +5002,14 @@ pub struct ComparisonExpression {
 }
 
 impl ComparisonExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "comparison_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "comparison_expression")?;
 
-        Self {
-            value: Box::new(ValueExpression::new(node.first_c())),
-            comparison: get_comparsion(&node),
+        Ok(Self {
+            value: Box::new(ValueExpression::new(node.first_c()?)?),
+            comparison: get_comparsion(&node)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4781,17 +5055,17 @@ pub struct ComparableList {
 }
 
 impl ComparableList {
-    pub fn new(node: Node) -> Self {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
         let values = node
             .children_vec()
             .into_iter()
-            .map(|n| ComparableListValue::new(n))
-            .collect();
+            .map(ComparableListValue::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             values,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4816,19 +5090,19 @@ pub struct OrderByClause {
 }
 
 impl OrderByClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "order_by_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "order_by_clause")?;
 
         let exps = node
-            .cs_by_k("order_expression")
+            .cs_by_k("order_expression")?
             .into_iter()
-            .map(|n| OrderExpression::new(n))
-            .collect();
+            .map(OrderExpression::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             exps,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4854,22 +5128,24 @@ pub struct OrderExpression {
 }
 
 impl OrderExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "order_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "order_expression")?;
 
         let direction = node
             .try_c_by_k("order_direction")
-            .map(|n| ValueNode::new(n));
+            .map(ValueNode::new)
+            .transpose()?;
         let null_direction = node
             .try_c_by_k("order_null_direction")
-            .map(|n| ValueNode::new(n));
+            .map(ValueNode::new)
+            .transpose()?;
 
-        Self {
-            value_expression: ValueExpression::new(node.first_c()),
+        Ok(Self {
+            value_expression: ValueExpression::new(node.first_c()?)?,
             direction,
             null_direction,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4897,12 +5173,12 @@ pub struct SubQuery {
 }
 
 impl SubQuery {
-    pub fn new(node: Node) -> Self {
-        let soql_query_body = Box::new(SoqlQueryBody::new(node.c_by_k("soql_query_body")));
-        Self {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        let soql_query_body = Box::new(SoqlQueryBody::new(node.c_by_k("soql_query_body")?)?);
+        Ok(Self {
             soql_query_body,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4927,14 +5203,14 @@ pub struct MapCreationExpression {
 }
 
 impl MapCreationExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "map_creation_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "map_creation_expression")?;
 
-        Self {
-            type_: SimpleType::new(node.c_by_n("type")),
-            value: MapInitializer::new(node.c_by_n("value")),
+        Ok(Self {
+            type_: SimpleType::new(node.c_by_n("type")?)?,
+            value: MapInitializer::new(node.c_by_n("value")?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4955,19 +5231,19 @@ pub struct MapInitializer {
 }
 
 impl MapInitializer {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "map_initializer");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "map_initializer")?;
 
         let initializers: Vec<_> = node
             .children_vec()
             .into_iter()
-            .map(|n| MapKeyInitializer::new(n))
-            .collect();
+            .map(MapKeyInitializer::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             initializers,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -4993,19 +5269,19 @@ pub struct MapKeyInitializer {
 }
 
 impl MapKeyInitializer {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "map_key_initializer");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "map_key_initializer")?;
 
         let children = node.children_vec();
         if children.len() != 2 {
-            panic!("### must be exactly 2 child nodes in MapKeyInitializer");
+            return Err(missing_child(&node, "key or value"));
         }
 
-        Self {
-            exp1: Box::new(Expression::new(children[0])),
-            exp2: Box::new(Expression::new(children[1])),
+        Ok(Self {
+            exp1: Box::new(Expression::new(children[0])?),
+            exp2: Box::new(Expression::new(children[1])?),
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5027,8 +5303,8 @@ pub struct GroupByClause {
 }
 
 impl GroupByClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "group_by_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "group_by_clause")?;
 
         let mut exps = Vec::new();
         let mut have_clause = None;
@@ -5036,24 +5312,22 @@ impl GroupByClause {
         for child in node.children_vec() {
             match child.kind() {
                 "field_identifier" => {
-                    exps.push(GroupByExpression::Field(FieldIdentifier::new(child)));
+                    exps.push(GroupByExpression::Field(FieldIdentifier::new(child)?));
                 }
                 "function_expression" => {
-                    exps.push(GroupByExpression::Func(FunctionExpression::new(child)));
+                    exps.push(GroupByExpression::Func(FunctionExpression::new(child)?));
                 }
                 "having_clause" => {
-                    have_clause = Some(HavingClause::new(child));
-                }
-                other => {
-                    panic!("## unknown node: {} in GroupByClause", red(other));
+                    have_clause = Some(HavingClause::new(child)?);
                 }
+                _ => return Err(unsupported_node(child, "GroupByClause")),
             }
         }
-        Self {
+        Ok(Self {
             exps,
             have_clause,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5101,13 +5375,13 @@ pub struct HavingClause {
 }
 
 impl HavingClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "having_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "having_clause")?;
 
-        Self {
-            boolean_exp: BooleanExpression::new(node.first_c()),
+        Ok(Self {
+            boolean_exp: BooleanExpression::new(node.first_c()?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5131,13 +5405,13 @@ pub struct SoslWithClause {
 }
 
 impl SoslWithClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "with_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "with_clause")?;
 
-        Self {
-            with_type: SoslWithType::new(node.c_by_k("with_type")),
+        Ok(Self {
+            with_type: SoslWithType::new(node.c_by_k("with_type")?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5157,13 +5431,13 @@ pub struct SoqlWithClause {
 }
 
 impl SoqlWithClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "with_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "with_clause")?;
 
-        Self {
-            with_type: SoqlWithType::new(node.c_by_k("with_type")),
+        Ok(Self {
+            with_type: SoqlWithType::new(node.c_by_k("with_type")?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5185,17 +5459,19 @@ pub enum SoqlWithTypeVariant {
 }
 
 impl SoqlWithTypeVariant {
-    pub fn new(node: Node) -> Self {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
         let with_type = if node.named_child_count() == 0 {
-            return Self::SimpleType(ValueNode::new(node));
+            return Ok(Self::SimpleType(ValueNode::new(node)?));
         } else {
-            let child = node.first_c();
+            let child = node.first_c()?;
             match child.kind() {
-                "with_user_id_type" => Self::UserId(ValueNode::new(child.c_by_k("string_literal"))),
-                _ => panic_unknown_node(node, "WithType"),
+                "with_user_id_type" => {
+                    Self::UserId(ValueNode::new(child.c_by_k("string_literal")?)?)
+                }
+                _ => return Err(unsupported_node(node, "WithType")),
             }
         };
-        with_type
+        Ok(with_type)
     }
 }
 
@@ -5226,23 +5502,23 @@ pub enum SoslWithType {
 }
 
 impl SoslWithType {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "with_type");
-
-        let child = node.first_c();
-        match child.kind() {
-            "with_data_cat_expression" => Self::DataCat(WithDataCatExpression::new(child)),
-            "with_division_expression" => Self::Division(WithDivisionExpression::new(child)),
-            "with_snippet_expression" => Self::Snippet(WithSnippetExpression::new(child)),
-            "with_network_expression" => Self::Network(WithNetworkExpression::new(child)),
-            "with_metadata_expression" => Self::Metadata(WithMetadataExpression::new(child)),
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "with_type")?;
+
+        let child = node.first_c()?;
+        Ok(match child.kind() {
+            "with_data_cat_expression" => Self::DataCat(WithDataCatExpression::new(child)?),
+            "with_division_expression" => Self::Division(WithDivisionExpression::new(child)?),
+            "with_snippet_expression" => Self::Snippet(WithSnippetExpression::new(child)?),
+            "with_network_expression" => Self::Network(WithNetworkExpression::new(child)?),
+            "with_metadata_expression" => Self::Metadata(WithMetadataExpression::new(child)?),
             "with_spell_correction_expression" => {
-                Self::Spell(WithSpellCorrectionExpression::new(child))
+                Self::Spell(WithSpellCorrectionExpression::new(child)?)
             }
             "with_highlight" => Self::Highlight,
-            "with_pricebook_expression" => Self::PriceBook(WithPriceBookExpression::new(child)),
-            _ => panic_unknown_node(child, "SoslWithType"),
-        }
+            "with_pricebook_expression" => Self::PriceBook(WithPriceBookExpression::new(child)?),
+            _ => return Err(unsupported_node(child, "SoslWithType")),
+        })
     }
 }
 
@@ -5284,19 +5560,19 @@ pub struct WithDataCatExpression {
 }
 
 impl WithDataCatExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "with_data_cat_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "with_data_cat_expression")?;
 
         let filters = node
-            .cs_by_k("with_data_cat_filter")
+            .cs_by_k("with_data_cat_filter")?
             .into_iter()
-            .map(|n| WithDataCatFilter::new(n))
-            .collect();
+            .map(WithDataCatFilter::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             filters,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5323,27 +5599,27 @@ pub struct WithDataCatFilter {
 }
 
 impl WithDataCatFilter {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "with_data_cat_filter");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "with_data_cat_filter")?;
 
-        let all_identififers = node.cs_by_k("identifier");
+        let all_identififers = node.cs_by_k("identifier")?;
         if all_identififers.len() < 2 {
-            panic!("At least 2 identifier nodes should exist in WithDataCatFilter");
+            return Err(missing_child(&node, "identifier"));
         }
 
-        let identifier = ValueNode::new(all_identififers[0]);
+        let identifier = ValueNode::new(all_identififers[0])?;
         let identifiers: Vec<_> = all_identififers
             .into_iter()
             .skip(1)
-            .map(|n| ValueNode::new(n))
-            .collect();
+            .map(ValueNode::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             identifier,
-            filter_type: ValueNodeUpperCase::new(node.c_by_k("with_data_cat_filter_type")),
+            filter_type: ValueNodeUpperCase::new(node.c_by_k("with_data_cat_filter_type")?)?,
             identifiers,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5377,15 +5653,15 @@ pub enum WithDivisionExpression {
 }
 
 impl WithDivisionExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "with_division_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "with_division_expression")?;
 
-        let child = node.first_c();
-        match child.kind() {
-            "bound_apex_expression" => Self::Bound(BoundApexExpression::new(child)),
+        let child = node.first_c()?;
+        Ok(match child.kind() {
+            "bound_apex_expression" => Self::Bound(BoundApexExpression::new(child)?),
             "string_literal" => Self::StringLiteral(child.value()),
-            _ => panic_unknown_node(node, "WithDivisionExpression"),
-        }
+            _ => return Err(unsupported_node(node, "WithDivisionExpression")),
+        })
     }
 }
 
@@ -5410,15 +5686,15 @@ pub struct WithSnippetExpression {
 }
 
 impl WithSnippetExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "with_snippet_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "with_snippet_expression")?;
 
-        let int = node.try_c_by_k("int").map(|n| ValueNode::new(n));
+        let int = node.try_c_by_k("int").map(ValueNode::new).transpose()?;
 
-        Self {
+        Ok(Self {
             int,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5443,13 +5719,13 @@ pub struct WithNetworkExpression {
 }
 
 impl WithNetworkExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "with_network_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "with_network_expression")?;
 
-        Self {
-            comparison: get_comparsion(&node),
+        Ok(Self {
+            comparison: get_comparsion(&node)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5469,13 +5745,13 @@ pub struct WithMetadataExpression {
 }
 
 impl WithMetadataExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "with_metadata_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "with_metadata_expression")?;
 
-        Self {
-            string_literal: ValueNode::new(node.c_by_k("string_literal")),
+        Ok(Self {
+            string_literal: ValueNode::new(node.c_by_k("string_literal")?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5495,13 +5771,13 @@ pub struct WithSpellCorrectionExpression {
 }
 
 impl WithSpellCorrectionExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "with_spell_correction_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "with_spell_correction_expression")?;
 
-        Self {
-            boolean: ValueNode::new(node.c_by_k("boolean")),
+        Ok(Self {
+            boolean: ValueNode::new(node.c_by_k("boolean")?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5521,13 +5797,13 @@ pub struct WithPriceBookExpression {
 }
 
 impl WithPriceBookExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "with_pricebook_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "with_pricebook_expression")?;
 
-        Self {
-            string_literal: ValueNode::new(node.c_by_k("string_literal")),
+        Ok(Self {
+            string_literal: ValueNode::new(node.c_by_k("string_literal")?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5547,19 +5823,19 @@ pub struct DottedIdentifier {
 }
 
 impl DottedIdentifier {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "dotted_identifier");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "dotted_identifier")?;
 
         let identifiers = node
-            .cs_by_k("identifier")
+            .cs_by_k("identifier")?
             .into_iter()
-            .map(|n| ValueNode::new(n))
-            .collect();
+            .map(ValueNode::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             identifiers,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5583,11 +5859,11 @@ pub struct ValueNode {
 }
 
 impl ValueNode {
-    pub fn new(node: Node) -> Self {
-        Self {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        Ok(Self {
             value: node.value(),
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5606,11 +5882,11 @@ pub struct ValueNodeLowerCase {
 }
 
 impl ValueNodeLowerCase {
-    pub fn new(node: Node) -> Self {
-        Self {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        Ok(Self {
             value: node.value(),
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5629,11 +5905,11 @@ pub struct ValueNodeUpperCase {
 }
 
 impl ValueNodeUpperCase {
-    pub fn new(node: Node) -> Self {
-        Self {
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        Ok(Self {
             value: node.value(),
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5652,13 +5928,13 @@ pub struct ExpressionStatement {
 }
 
 impl ExpressionStatement {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "expression_statement");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "expression_statement")?;
 
-        Self {
-            exp: Expression::new(node.first_c()),
+        Ok(Self {
+            exp: Expression::new(node.first_c()?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5676,12 +5952,12 @@ pub struct SafeNavigationOperator {
 }
 
 impl SafeNavigationOperator {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "safe_navigation_operator");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "safe_navigation_operator")?;
 
-        Self {
+        Ok(Self {
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5700,13 +5976,13 @@ pub struct CountExpression {
 }
 
 impl CountExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "count_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "count_expression")?;
 
-        Self {
-            function_name: ValueNode::new(node.c_by_n("function_name")),
+        Ok(Self {
+            function_name: ValueNode::new(node.c_by_n("function_name")?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5726,13 +6002,13 @@ pub struct FunctionExpression {
 }
 
 impl FunctionExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "function_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "function_expression")?;
 
-        Self {
-            variant: FunctionExpressionVariant::new(node),
+        Ok(Self {
+            variant: FunctionExpressionVariant::new(node)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5751,13 +6027,13 @@ pub struct FieldIdentifier {
 }
 
 impl FieldIdentifier {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "field_identifier");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "field_identifier")?;
 
-        Self {
-            variant: FieldIdentifierVariant::new(node),
+        Ok(Self {
+            variant: FieldIdentifierVariant::new(node)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5776,13 +6052,13 @@ pub struct GeoLocationType {
 }
 
 impl GeoLocationType {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "geo_location_type");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "geo_location_type")?;
 
-        Self {
-            variant: GeoLocationTypeVariant::new(node),
+        Ok(Self {
+            variant: GeoLocationTypeVariant::new(node)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5801,13 +6077,13 @@ pub struct SelectClause {
 }
 
 impl SelectClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "select_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "select_clause")?;
 
-        Self {
-            variant: SelectClauseVariant::new(node),
+        Ok(Self {
+            variant: SelectClauseVariant::new(node)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5826,13 +6102,13 @@ pub struct StorageIdentifier {
 }
 
 impl StorageIdentifier {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "storage_identifier");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "storage_identifier")?;
 
-        Self {
-            variant: StorageIdentifierVariant::new(node),
+        Ok(Self {
+            variant: StorageIdentifierVariant::new(node)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5851,19 +6127,19 @@ pub struct AndExpression {
 }
 
 impl AndExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "and_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "and_expression")?;
 
         let condition_exps = node
             .children_vec()
             .into_iter()
-            .map(|n| ConditionExpression::new(n))
-            .collect();
+            .map(ConditionExpression::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             condition_exps,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5888,19 +6164,19 @@ pub struct OrExpression {
 }
 
 impl OrExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "or_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "or_expression")?;
 
         let condition_exps = node
             .children_vec()
             .into_iter()
-            .map(|n| ConditionExpression::new(n))
-            .collect();
+            .map(ConditionExpression::new)
+            .collect::<Result<_, _>>()?;
 
-        Self {
+        Ok(Self {
             condition_exps,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5925,13 +6201,13 @@ pub struct NotExpression {
 }
 
 impl NotExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "not_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "not_expression")?;
 
-        Self {
-            condition_exp: ConditionExpression::new(node.first_c()),
+        Ok(Self {
+            condition_exp: ConditionExpression::new(node.first_c()?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5952,13 +6228,13 @@ pub struct SoqlWithType {
 }
 
 impl SoqlWithType {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "with_type");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "with_type")?;
 
-        Self {
-            variant: SoqlWithTypeVariant::new(node),
+        Ok(Self {
+            variant: SoqlWithTypeVariant::new(node)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -5977,13 +6253,13 @@ pub struct ForClause {
 }
 
 impl ForClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "for_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "for_clause")?;
 
-        Self {
-            for_type: ValueNode::new(node.c_by_k("for_type")),
+        Ok(Self {
+            for_type: ValueNode::new(node.c_by_k("for_type")?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -6001,12 +6277,12 @@ pub struct AllRowsClause {
 }
 
 impl AllRowsClause {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "all_rows_clause");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "all_rows_clause")?;
 
-        Self {
+        Ok(Self {
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -6025,13 +6301,13 @@ pub struct UpdateExpression {
 }
 
 impl UpdateExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "update_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "update_expression")?;
 
-        Self {
-            variant: UpdateExpressionVariant::new(node),
+        Ok(Self {
+            variant: UpdateExpressionVariant::new(node)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -6050,13 +6326,13 @@ pub struct DmlExpression {
 }
 
 impl DmlExpression {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "dml_expression");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "dml_expression")?;
 
-        Self {
-            variant: DmlExpressionVariant::new(node),
+        Ok(Self {
+            variant: DmlExpressionVariant::new(node)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
@@ -6075,13 +6351,13 @@ pub struct DmlType {
 }
 
 impl DmlType {
-    pub fn new(node: Node) -> Self {
-        assert_check(node, "dml_type");
+    pub fn new(node: Node) -> Result<Self, FormatError> {
+        assert_check(node, "dml_type")?;
 
-        Self {
-            variant: DmlTypeVariant::new(node.first_c()),
+        Ok(Self {
+            variant: DmlTypeVariant::new(node.first_c()?)?,
             node_context: NodeContext::with_punctuation(&node),
-        }
+        })
     }
 }
 
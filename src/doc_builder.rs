@@ -185,7 +185,7 @@ impl<'a> DocBuilder<'a> {
         self.concat(vec![self.nl_with_no_indent(), self.nl()])
     }
 
-    fn nl_with_no_indent(&'a self) -> DocRef<'a> {
+    pub fn nl_with_no_indent(&'a self) -> DocRef<'a> {
         self.arena.alloc(Doc::NewlineWithNoIndent)
     }
 
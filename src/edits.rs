@@ -0,0 +1,200 @@
+use serde::Serialize;
+use similar::algorithms::{diff_deadline, Algorithm, Capture};
+use similar::DiffOp;
+use std::time::{Duration, Instant};
+
+// A diff taking longer falls back to coarser, still correct, edits.
+const DIFF_TIMEOUT: Duration = Duration::from_millis(500);
+
+// A position as in LSP: 0-based line, and character offset in UTF-16 code units.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Position {
+    pub line: u32,
+    pub character: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+// Replace `range` of the original text with `new_text`; serializes like an LSP `TextEdit`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextEdit {
+    pub range: Range,
+    pub new_text: String,
+}
+
+// The edits turning `original` into `formatted`, in order and not overlapping, so they can be
+// applied at once. Changed lines are diffed again by words and whitespace runs, so re-indenting
+// a line only touches its leading whitespace.
+pub fn text_edits(original: &str, formatted: &str) -> Vec<TextEdit> {
+    let deadline = Instant::now() + DIFF_TIMEOUT;
+    let mut changes = Vec::new();
+    for (old_lines, new_lines) in diff(original, formatted, split_lines, deadline) {
+        let old_text = &original[old_lines.clone()];
+        let new_text = &formatted[new_lines.clone()];
+        for (old, new) in diff(old_text, new_text, split_words, deadline) {
+            let old = old.start + old_lines.start..old.end + old_lines.start;
+            let new = new.start + new_lines.start..new.end + new_lines.start;
+            changes.push(trim_common(original, formatted, old, new));
+        }
+    }
+
+    let line_starts = line_starts(original);
+    changes
+        .into_iter()
+        .map(|(old, new)| TextEdit {
+            range: Range {
+                start: position_at(original, &line_starts, old.start),
+                end: position_at(original, &line_starts, old.end),
+            },
+            new_text: formatted[new].to_string(),
+        })
+        .collect()
+}
+
+// The position of byte offset `byte` in `text`.
+pub fn position(text: &str, byte: usize) -> Position {
+    position_at(text, &line_starts(text), byte)
+}
+
+// The byte offset of `position` in `text`, clamped to the end of its line.
+pub fn offset(text: &str, position: Position) -> usize {
+    let Some(&line_start) = line_starts(text).get(position.line as usize) else {
+        return text.len();
+    };
+
+    let mut units = 0;
+    for (i, c) in text[line_start..].char_indices() {
+        if units >= position.character as usize || c == '\n' {
+            return line_start + i;
+        }
+        units += c.len_utf16();
+    }
+    text.len()
+}
+
+// Shrink a change to the part that differs, e.g. a whitespace run `\n    ` becoming `\n  `.
+fn trim_common(
+    original: &str,
+    formatted: &str,
+    mut old: std::ops::Range<usize>,
+    mut new: std::ops::Range<usize>,
+) -> (std::ops::Range<usize>, std::ops::Range<usize>) {
+    let prefix: usize = original[old.clone()]
+        .chars()
+        .zip(formatted[new.clone()].chars())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a.len_utf8())
+        .sum();
+    old.start += prefix;
+    new.start += prefix;
+
+    let suffix: usize = original[old.clone()]
+        .chars()
+        .rev()
+        .zip(formatted[new.clone()].chars().rev())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a.len_utf8())
+        .sum();
+    old.end -= suffix;
+    new.end -= suffix;
+    (old, new)
+}
+
+// The byte ranges of `old` replaced by byte ranges of `new`, adjacent and overlapping changes
+// merged. Past the deadline the rest is one change.
+fn diff<'a>(
+    old: &'a str,
+    new: &'a str,
+    split: fn(&'a str) -> Vec<&'a str>,
+    deadline: Instant,
+) -> Vec<(std::ops::Range<usize>, std::ops::Range<usize>)> {
+    let old_slices = split(old);
+    let new_slices = split(new);
+    let old_offsets = offsets(&old_slices);
+    let new_offsets = offsets(&new_slices);
+
+    // `TextDiff` compacts its ops, which can leave them out of order
+    let mut capture = Capture::new();
+    let Ok(()) = diff_deadline(
+        Algorithm::Myers,
+        &mut capture,
+        &old_slices,
+        0..old_slices.len(),
+        &new_slices,
+        0..new_slices.len(),
+        Some(deadline),
+    );
+
+    let mut changes: Vec<(std::ops::Range<usize>, std::ops::Range<usize>)> = Vec::new();
+    for op in capture.into_ops() {
+        if let DiffOp::Equal { .. } = op {
+            continue;
+        }
+        let old = old_offsets[op.old_range().start]..old_offsets[op.old_range().end];
+        let new = new_offsets[op.new_range().start]..new_offsets[op.new_range().end];
+        match changes.last_mut() {
+            Some((last_old, last_new)) if old.start <= last_old.end => {
+                last_old.end = last_old.end.max(old.end);
+                last_new.end = last_new.end.max(new.end);
+            }
+            _ => changes.push((old, new)),
+        }
+    }
+    changes
+}
+
+fn split_lines(text: &str) -> Vec<&str> {
+    text.split_inclusive('\n').collect()
+}
+
+// Runs of whitespace and of other characters.
+fn split_words(text: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut start = 0;
+    let mut in_whitespace = None;
+    for (i, c) in text.char_indices() {
+        let whitespace = c.is_whitespace();
+        if in_whitespace.is_some_and(|w| w != whitespace) {
+            words.push(&text[start..i]);
+            start = i;
+        }
+        in_whitespace = Some(whitespace);
+    }
+    if start < text.len() {
+        words.push(&text[start..]);
+    }
+    words
+}
+
+// Byte offset of every slice, plus the end of the last one.
+fn offsets(slices: &[&str]) -> Vec<usize> {
+    let mut offsets = Vec::with_capacity(slices.len() + 1);
+    let mut offset = 0;
+    offsets.push(offset);
+    for slice in slices {
+        offset += slice.len();
+        offsets.push(offset);
+    }
+    offsets
+}
+
+fn line_starts(text: &str) -> Vec<usize> {
+    std::iter::once(0)
+        .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+        .collect()
+}
+
+fn position_at(text: &str, line_starts: &[usize], byte: usize) -> Position {
+    let line = line_starts.partition_point(|&start| start <= byte) - 1;
+    let line_start = line_starts[line];
+    let character: usize = text[line_start..byte].chars().map(char::len_utf16).sum();
+    Position {
+        line: line as u32,
+        character: character as u32,
+    }
+}
@@ -0,0 +1,279 @@
+use crate::accessor::Accessor;
+use crate::context::FormatContext;
+use crate::utility::truncate_snippet;
+use std::fmt;
+use tree_sitter::Node;
+
+// Where in the source an error was detected. `line` and `column` are 1-based.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ErrorLocation {
+    pub file: Option<String>,
+    pub line: usize,
+    pub column: usize,
+    pub snippet: String,
+}
+
+impl ErrorLocation {
+    // Locate `byte` in `source_code`; the column counts characters, not bytes.
+    pub fn at(source_code: &str, byte: usize, snippet: &str) -> Self {
+        let line_start = line_start(source_code, byte);
+        Self {
+            file: None,
+            line: source_code[..line_start].matches('\n').count() + 1,
+            column: source_code[line_start..byte].chars().count() + 1,
+            snippet: truncate_snippet(snippet),
+        }
+    }
+
+    // Requires an entered `FormatContext` to read the node's source text.
+    pub fn from_node(node: &Node) -> Self {
+        let context = FormatContext::current();
+        Self::at(context.source_code(), node.start_byte(), &node.value())
+    }
+}
+
+fn line_start(source_code: &str, byte: usize) -> usize {
+    source_code[..byte].rfind('\n').map_or(0, |i| i + 1)
+}
+
+// One ERROR or MISSING node found by the parser, rendered like a rustc diagnostic.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SyntaxDiagnostic {
+    pub message: String,
+    pub location: ErrorLocation,
+    pub source_line: String,
+    pub underline_width: usize,
+}
+
+impl SyntaxDiagnostic {
+    pub fn from_node(node: &Node, source_code: &str) -> Self {
+        let start = node.start_byte();
+        let text = &source_code[start..node.end_byte()];
+
+        let message = if node.is_missing() {
+            format!("missing `{}`", node.kind())
+        } else if text.trim().is_empty() {
+            "unexpected input".to_string()
+        } else {
+            format!("unexpected `{}`", truncate_snippet(text.trim()))
+        };
+
+        let line_start = line_start(source_code, start);
+        let line_end = source_code[start..]
+            .find('\n')
+            .map_or(source_code.len(), |i| start + i);
+        let underlined = &text[..text.len().min(line_end - start)];
+
+        Self {
+            message,
+            location: ErrorLocation::at(source_code, start, text),
+            source_line: source_code[line_start..line_end].trim_end().to_string(),
+            underline_width: underlined.chars().count().max(1),
+        }
+    }
+}
+
+impl fmt::Display for SyntaxDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let line_no = self.location.line.to_string();
+        let gutter = " ".repeat(line_no.len());
+
+        writeln!(f, "error: {}", self.message)?;
+        writeln!(f, "{}--> {}", gutter, self.location)?;
+        writeln!(f, "{} |", gutter)?;
+        writeln!(f, "{} | {}", line_no, self.source_line)?;
+        write!(
+            f,
+            "{} | {}{}",
+            gutter,
+            " ".repeat(self.location.column - 1),
+            "^".repeat(self.underline_width)
+        )
+    }
+}
+
+impl fmt::Display for ErrorLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let file = self.file.as_deref().unwrap_or("<source>");
+        write!(f, "{}:{}:{}", file, self.line, self.column)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FormatError {
+    // The parser produced ERROR or MISSING nodes, in source order.
+    Syntax(Vec<SyntaxDiagnostic>),
+    // A node kind the formatter does not know how to print.
+    UnsupportedNode {
+        kind: String,
+        within: String,
+        location: ErrorLocation,
+    },
+    // Comments that were not printed, so writing the result would lose them; in source order.
+    ErasedComments(Vec<ErrorLocation>),
+    // The source file could not be read.
+    Io {
+        file: String,
+        message: String,
+    },
+    // `--verify` found the formatted code to differ from the source beyond whitespace.
+    Verification {
+        location: ErrorLocation,
+        message: String,
+    },
+    // `--verify-idempotency` found that formatting the result again changes it.
+    Unstable {
+        file: Option<String>,
+        diff: String, // from the first to the second pass
+    },
+    // The config file found for the source file is invalid.
+    Config {
+        file: String,
+        message: String,
+    },
+    // Any other unexpected failure inside the formatter.
+    Internal {
+        file: Option<String>,
+        message: String,
+    },
+}
+
+impl FormatError {
+    // Attach the file path to errors created without one.
+    pub fn with_file(mut self, path: &str) -> Self {
+        match &mut self {
+            Self::Syntax(diagnostics) => {
+                for diagnostic in diagnostics {
+                    let file = &mut diagnostic.location.file;
+                    file.get_or_insert_with(|| path.to_string());
+                }
+            }
+            Self::ErasedComments(locations) => {
+                for location in locations {
+                    location.file.get_or_insert_with(|| path.to_string());
+                }
+            }
+            Self::UnsupportedNode { location, .. } | Self::Verification { location, .. } => {
+                location.file.get_or_insert_with(|| path.to_string());
+            }
+            Self::Internal { file, .. } | Self::Unstable { file, .. } => {
+                file.get_or_insert_with(|| path.to_string());
+            }
+            Self::Io { .. } | Self::Config { .. } => {}
+        }
+        self
+    }
+
+    // A short, stable name of the variant.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Syntax(_) => "syntax",
+            Self::UnsupportedNode { .. } => "unsupported_node",
+            Self::ErasedComments(_) => "erased_comment",
+            Self::Verification { .. } => "verification",
+            Self::Unstable { .. } => "unstable",
+            Self::Io { .. } => "io",
+            Self::Config { .. } => "config",
+            Self::Internal { .. } => "internal",
+        }
+    }
+
+    // The first location of the error, if it has one.
+    pub fn location(&self) -> Option<&ErrorLocation> {
+        match self {
+            Self::Syntax(diagnostics) => diagnostics.first().map(|d| &d.location),
+            Self::ErasedComments(locations) => locations.first(),
+            Self::UnsupportedNode { location, .. } | Self::Verification { location, .. } => {
+                Some(location)
+            }
+            Self::Io { .. }
+            | Self::Config { .. }
+            | Self::Internal { .. }
+            | Self::Unstable { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Syntax(diagnostics) => {
+                for (i, diagnostic) in diagnostics.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "\n\n")?;
+                    }
+                    write!(f, "{}", diagnostic)?;
+                }
+                Ok(())
+            }
+            Self::UnsupportedNode {
+                kind,
+                within,
+                location,
+            } => write!(
+                f,
+                "{}: unsupported node `{}` in {}: `{}`",
+                location, kind, within, location.snippet
+            ),
+            Self::ErasedComments(locations) => {
+                for (i, location) in locations.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(
+                        f,
+                        "{}: comment would be erased by formatting: `{}`",
+                        location, location.snippet
+                    )?;
+                }
+                Ok(())
+            }
+            Self::Verification { location, message } => write!(
+                f,
+                "{}: formatting would change the code: {}",
+                location, message
+            ),
+            Self::Unstable { file, diff } => write!(
+                f,
+                "{}: formatting is not idempotent, a second pass changes it:\n{}",
+                file.as_deref().unwrap_or("<source>"),
+                diff
+            ),
+            Self::Io { file, message } | Self::Config { file, message } => {
+                write!(f, "{}: {}", file, message)
+            }
+            Self::Internal { file, message } => write!(
+                f,
+                "{}: internal error: {}",
+                file.as_deref().unwrap_or("<source>"),
+                message
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FormatError {}
+
+// Something formatted differently than asked for, e.g. a member kept as written in recovery mode.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FormatWarning {
+    pub message: String,
+    pub location: ErrorLocation,
+}
+
+impl FormatWarning {
+    pub fn with_file(mut self, path: &str) -> Self {
+        self.location.file.get_or_insert_with(|| path.to_string());
+        self
+    }
+}
+
+impl fmt::Display for FormatWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {}: `{}`",
+            self.location, self.message, self.location.snippet
+        )
+    }
+}
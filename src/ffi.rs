@@ -0,0 +1,112 @@
+// C ABI for hosts embedding afmt in-process; `include/afmt.h` is generated from this file with
+// `cbindgen --config cbindgen.toml --output include/afmt.h`.
+use crate::formatter::{Config, Formatter};
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+// Return codes of `afmt_format`, the same as the exit codes of the CLI.
+/// The code was formatted.
+pub const AFMT_OK: c_int = 0;
+/// The code could not be parsed or formatted.
+pub const AFMT_FORMAT_ERROR: c_int = 2;
+/// A NULL pointer, source code or config that is not UTF-8, or an invalid config.
+pub const AFMT_INVALID_ARGUMENT: c_int = 3;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Formats the `len` bytes of UTF-8 Apex code at `src`.
+///
+/// `config_toml` is the content of an `.afmt.toml` file as a NUL-terminated string, or NULL for
+/// the defaults. On success, `*out` receives the NUL-terminated formatted code, to be released
+/// with `afmt_free`, and `AFMT_OK` is returned. Otherwise `*out` is set to NULL and the error
+/// message is available from `afmt_last_error`.
+///
+/// # Safety
+///
+/// `src` must point to `len` readable bytes, `config_toml` must be NULL or a NUL-terminated
+/// string, and `out` must be a valid pointer to write to.
+#[no_mangle]
+pub unsafe extern "C" fn afmt_format(
+    src: *const c_char,
+    len: usize,
+    config_toml: *const c_char,
+    out: *mut *mut c_char,
+) -> c_int {
+    if out.is_null() {
+        return fail(AFMT_INVALID_ARGUMENT, "`out` is NULL");
+    }
+    *out = ptr::null_mut();
+    if src.is_null() {
+        return fail(AFMT_INVALID_ARGUMENT, "`src` is NULL");
+    }
+
+    let source = std::slice::from_raw_parts(src.cast::<u8>(), len);
+    let Ok(source) = std::str::from_utf8(source) else {
+        return fail(AFMT_INVALID_ARGUMENT, "the source code is not valid UTF-8");
+    };
+    let config = if config_toml.is_null() {
+        Ok(Config::default())
+    } else {
+        match CStr::from_ptr(config_toml).to_str() {
+            Ok(toml) => Config::from_toml(toml),
+            Err(_) => Err("the config is not valid UTF-8".to_string()),
+        }
+    };
+    let config = match config {
+        Ok(config) => config,
+        Err(message) => return fail(AFMT_INVALID_ARGUMENT, &message),
+    };
+
+    // errors come back as `Err`; only a bug in afmt panics, which must not unwind into the host
+    let result = panic::catch_unwind(AssertUnwindSafe(|| Formatter::format_one(source, config)));
+    match result {
+        Ok(Ok(formatted)) => match CString::new(formatted) {
+            Ok(formatted) => {
+                set_last_error(None);
+                *out = formatted.into_raw();
+                AFMT_OK
+            }
+            Err(_) => fail(AFMT_FORMAT_ERROR, "the formatted code contains a NUL byte"),
+        },
+        Ok(Err(e)) => fail(AFMT_FORMAT_ERROR, &e.to_string()),
+        Err(_) => fail(AFMT_FORMAT_ERROR, "internal error: afmt panicked"),
+    }
+}
+
+/// Releases a string returned by `afmt_format`; NULL is ignored.
+///
+/// # Safety
+///
+/// `s` must be NULL or a string returned by `afmt_format` that was not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn afmt_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// The message of the last failed `afmt_format` call on this thread, or NULL if it succeeded.
+/// The string is owned by afmt and valid until the next `afmt_format` call on this thread.
+#[no_mangle]
+pub extern "C" fn afmt_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+fn fail(code: c_int, message: &str) -> c_int {
+    // a message cannot hold NUL bytes in C
+    let message = CString::new(message.replace('\0', "\\0")).expect("NUL bytes are replaced");
+    set_last_error(Some(message));
+    code
+}
+
+fn set_last_error(message: Option<CString>) {
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+}
@@ -0,0 +1,178 @@
+use crate::message_helper::yellow;
+use glob::{MatchOptions, Pattern};
+use ignore::gitignore::Gitignore;
+use ignore::{Match, WalkBuilder};
+use std::collections::HashMap;
+use std::path::{self, Path, PathBuf};
+
+const SOURCE_EXTENSIONS: [&str; 2] = ["cls", "trigger"];
+const IGNORE_FILE_NAME: &str = ".afmtignore";
+// per directory, from the highest precedence to the lowest, as read by `WalkBuilder`
+const IGNORE_FILE_NAMES: [&str; 3] = [IGNORE_FILE_NAME, ".ignore", ".gitignore"];
+
+// Expand the CLI inputs (files, directories, glob patterns) into a sorted list of source files,
+// without those ignored by `.gitignore`, `.afmtignore` or `is_excluded`. Directories and globs
+// only pick up Apex sources; explicitly named files are kept whatever their extension, and
+// also when ignored with `include_ignored`.
+pub fn collect_source_files(
+    inputs: &[String],
+    include_ignored: bool,
+    is_excluded: impl Fn(&str) -> bool,
+) -> Result<Vec<String>, String> {
+    let mut files = Vec::new();
+
+    for input in inputs {
+        let path = Path::new(input);
+
+        if path.is_file() && include_ignored {
+            files.push(input.clone());
+        } else if path.is_file() {
+            files.extend(filter_ignored(vec![input.clone()], &is_excluded));
+        } else if path.is_dir() {
+            let found = walk_dir(path);
+            files.extend(found.into_iter().filter(|file| !is_excluded(file)));
+        } else if is_glob_pattern(input) {
+            let found = expand_glob(input)?;
+            files.extend(found.into_iter().filter(|file| !is_excluded(file)));
+        } else {
+            return Err(format!("Path not found: {}", yellow(input)));
+        }
+    }
+
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+// Keep the files listed by git that a directory walk would pick up as well, i.e. those not
+// ignored by `.afmtignore`, `.ignore` or `.gitignore` files nor by `is_excluded`.
+pub fn filter_ignored(files: Vec<String>, is_excluded: impl Fn(&str) -> bool) -> Vec<String> {
+    let mut ignore_files = IgnoreFiles::default();
+    files
+        .into_iter()
+        .filter(|file| !is_excluded(file) && !ignore_files.is_ignored(file))
+        .collect()
+}
+
+// The ignore files of every directory above a file, read once per directory.
+#[derive(Default)]
+struct IgnoreFiles {
+    dirs: HashMap<PathBuf, Vec<Gitignore>>,
+}
+
+impl IgnoreFiles {
+    // The deepest ignore file with a matching pattern decides, as for a directory walk.
+    fn is_ignored(&mut self, file: &str) -> bool {
+        let Ok(file) = path::absolute(file) else {
+            return false;
+        };
+        for dir in file.ancestors().skip(1) {
+            let ignores = self.dirs.entry(dir.to_path_buf()).or_insert_with(|| {
+                IGNORE_FILE_NAMES
+                    .iter()
+                    .map(|name| {
+                        let (ignore, error) = Gitignore::new(dir.join(name));
+                        if let Some(e) = error {
+                            eprintln!("Warning: {}", e);
+                        }
+                        ignore
+                    })
+                    .collect()
+            });
+            for ignore in ignores.iter() {
+                match ignore.matched_path_or_any_parents(&file, false) {
+                    Match::Ignore(_) => return true,
+                    Match::Whitelist(_) => return false,
+                    Match::None => {}
+                }
+            }
+        }
+        false
+    }
+}
+
+pub fn is_source_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| SOURCE_EXTENSIONS.contains(&ext))
+}
+
+fn is_glob_pattern(input: &str) -> bool {
+    input.contains(['*', '?', '['])
+}
+
+// Walk the literal directory prefix of the pattern so ignore files apply to globs as well.
+fn expand_glob(pattern: &str) -> Result<Vec<String>, String> {
+    let compiled = Pattern::new(pattern)
+        .map_err(|e| format!("Invalid glob pattern {}: {}", yellow(pattern), e))?;
+    let options = MatchOptions {
+        require_literal_separator: true,
+        ..MatchOptions::new()
+    };
+
+    let mut base = PathBuf::new();
+    for component in Path::new(pattern).components() {
+        if is_glob_pattern(&component.as_os_str().to_string_lossy()) {
+            break;
+        }
+        base.push(component);
+    }
+
+    let mut files = Vec::new();
+    if base.as_os_str().is_empty() || base.is_dir() {
+        let root = if base.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            &base
+        };
+        for file in walk_dir(root) {
+            // `*.cls` is matched against `A.cls`, not `./A.cls`
+            let path = if base.as_os_str().is_empty() {
+                Path::new(&file)
+                    .strip_prefix(".")
+                    .unwrap_or(Path::new(&file))
+            } else {
+                Path::new(&file)
+            };
+            // a matching directory selects every file below it
+            if path
+                .ancestors()
+                .any(|p| compiled.matches_path_with(p, options))
+            {
+                files.push(path.to_string_lossy().to_string());
+            }
+        }
+    }
+
+    if files.is_empty() {
+        return Err(format!("No files match pattern: {}", yellow(pattern)));
+    }
+    Ok(files)
+}
+
+// Errors, e.g. an unreadable directory or a bad line in an ignore file, are reported as warnings
+// and the rest of the directory is still walked.
+fn walk_dir(dir: &Path) -> Vec<String> {
+    let mut files = Vec::new();
+    let walker = WalkBuilder::new(dir)
+        .add_custom_ignore_filename(IGNORE_FILE_NAME)
+        .build();
+
+    for entry in walker {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                eprintln!("Warning: {}", e);
+                continue;
+            }
+        };
+        if let Some(e) = entry.error() {
+            eprintln!("Warning: {}", e);
+        }
+        let is_file = entry.file_type().is_some_and(|t| t.is_file());
+        if is_file && is_source_file(entry.path()) {
+            files.push(entry.path().to_string_lossy().to_string());
+        }
+    }
+    files
+}
@@ -1,21 +1,33 @@
-use crate::context::CommentMap;
+use crate::cache::Cache;
+use crate::context::FormatContext;
+use crate::cursor;
 use crate::data_model::*;
 use crate::doc::{pretty_print, PrettyConfig};
 use crate::doc_builder::DocBuilder;
-use crate::message_helper::{red, yellow};
-use crate::utility::{
-    assert_no_missing_comments, collect_comments, enrich, set_thread_comment_map,
-    set_thread_source_code, truncate_snippet,
-};
+use crate::error::{ErrorLocation, FormatError, FormatWarning, SyntaxDiagnostic};
+use crate::message_helper::yellow;
+use crate::orphans;
+use crate::range::{self, FormatRange};
+use crate::report;
+use crate::utility::{enrich, erased_comments, printed_comments};
+use crate::verify;
 use serde::Deserialize;
-use std::sync::mpsc;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 use std::{fs, path::Path};
 use tree_sitter::{Node, Parser, Tree};
 
 #[allow(unused_imports)]
 use crate::utility::print_comment_map;
 
+pub const CONFIG_FILE_NAME: &str = ".afmt.toml";
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct Config {
     #[serde(default = "default_max_width")]
@@ -23,6 +35,14 @@ pub struct Config {
 
     #[serde(default = "default_indent_size")]
     pub indent_size: u32,
+
+    // Glob patterns, relative to the config file, of files skipped when walking directories.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+
+    // `exclude`, compiled once when the config is loaded
+    #[serde(skip)]
+    exclude_patterns: Vec<glob::Pattern>,
 }
 
 fn default_max_width() -> u32 {
@@ -38,6 +58,8 @@ impl Default for Config {
         Self {
             max_width: default_max_width(),
             indent_size: default_indent_size(),
+            exclude: Vec::new(),
+            exclude_patterns: Vec::new(),
         }
     }
 }
@@ -47,15 +69,73 @@ impl Config {
         Self {
             max_width,
             indent_size: 2,
+            exclude: Vec::new(),
+            exclude_patterns: Vec::new(),
         }
     }
 
     pub fn from_file(path: &str) -> Result<Self, String> {
         let content =
             fs::read_to_string(path).map_err(|e| format!("Failed to read config file: {}", e))?;
+        Self::from_toml(&content)
+    }
+
+    // The content of an `.afmt.toml` file.
+    pub fn from_toml(toml: &str) -> Result<Self, String> {
         let config: Config =
-            toml::from_str(&content).map_err(|e| format!("Failed to parse config file: {}", e))?;
-        Ok(config)
+            toml::from_str(toml).map_err(|e| format!("Failed to parse config file: {}", e))?;
+        config.validate()
+    }
+
+    // The same settings as `.afmt.toml` as a JSON object, e.g. `{"max_width": 100}`.
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let config: Config =
+            serde_json::from_str(json).map_err(|e| format!("Failed to parse config: {}", e))?;
+        config.validate()
+    }
+
+    fn validate(mut self) -> Result<Self, String> {
+        if self.indent_size == 0 {
+            return Err("indent_size must be greater than 0".to_string());
+        }
+        self.exclude_patterns = self
+            .exclude
+            .iter()
+            .map(|pattern| {
+                glob::Pattern::new(pattern)
+                    .map_err(|e| format!("Invalid exclude pattern `{}`: {}", pattern, e))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(self)
+    }
+
+    // Find the nearest config file from `dir` upwards, stopping at the repository root.
+    pub fn find_file(dir: &Path) -> Option<PathBuf> {
+        let dir = fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+        for ancestor in dir.ancestors() {
+            let candidate = ancestor.join(CONFIG_FILE_NAME);
+            if candidate.is_file() {
+                return Some(candidate);
+            }
+            if ancestor.join(".git").exists() {
+                break;
+            }
+        }
+        None
+    }
+
+    // Whether `file` matches an `exclude` pattern; `base` is the directory of the config file.
+    pub fn excludes(&self, file: &Path, base: &Path) -> bool {
+        if self.exclude_patterns.is_empty() {
+            return false;
+        }
+
+        let file = fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf());
+        let base = fs::canonicalize(base).unwrap_or_else(|_| base.to_path_buf());
+        let relative = file.strip_prefix(&base).unwrap_or(&file);
+        self.exclude_patterns
+            .iter()
+            .any(|pattern| pattern.matches_path(relative))
     }
 
     pub fn max_width(&self) -> u32 {
@@ -67,10 +147,114 @@ impl Config {
     }
 }
 
+// The result of formatting one source file, kept in input order by `Formatter::format()`.
+#[derive(Clone, Debug)]
+pub struct FileOutcome {
+    pub path: String,
+    pub original: Option<String>, // None when the file could not be read
+    pub formatted: Option<String>,
+    pub changed: bool,
+    pub left_unformatted: bool, // kept as written by `CommentFallback::Leave`
+    pub error: Option<FormatError>,
+    pub warnings: Vec<FormatWarning>,
+    pub cursor_offset: Option<usize>, // `FormatOptions::cursor_offset` in the formatted code
+    pub duration: Duration,
+}
+
+impl FileOutcome {
+    pub fn formatted(path: &str, original: String, formatted: String) -> Self {
+        Self {
+            path: path.to_string(),
+            changed: original != formatted,
+            left_unformatted: false,
+            original: Some(original),
+            formatted: Some(formatted),
+            error: None,
+            warnings: Vec::new(),
+            cursor_offset: None,
+            duration: Duration::ZERO,
+        }
+    }
+
+    pub fn failed(path: &str, original: Option<String>, error: FormatError) -> Self {
+        Self {
+            path: path.to_string(),
+            original,
+            formatted: None,
+            changed: false,
+            left_unformatted: false,
+            error: Some(error.with_file(path)),
+            warnings: Vec::new(),
+            cursor_offset: None,
+            duration: Duration::ZERO,
+        }
+    }
+
+    fn new(path: &str, original: String, result: Result<Formatted, FormatError>) -> Self {
+        match result {
+            Ok(formatted) => {
+                let mut outcome = Self::formatted(path, original, formatted.code);
+                outcome.cursor_offset = formatted.cursor_offset;
+                outcome.left_unformatted = formatted.left_unformatted;
+                outcome.warnings = formatted
+                    .warnings
+                    .into_iter()
+                    .map(|warning| warning.with_file(path))
+                    .collect();
+                outcome
+            }
+            Err(e) => Self::failed(path, Some(original), e),
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.error.is_some()
+    }
+
+    // Whether `--check` and `--list-different` report the file.
+    pub fn needs_formatting(&self) -> bool {
+        self.changed || self.left_unformatted
+    }
+}
+
+// How files are formatted, beyond the style settings of `Config`.
+#[derive(Clone, Debug, Default)]
+pub struct FormatOptions {
+    pub range: Option<FormatRange>,
+    pub recover: bool,
+    pub verify: bool,
+    pub verify_idempotency: bool,
+    pub comment_fallback: CommentFallback,
+    pub cursor_offset: Option<usize>, // byte offset in the input to follow through formatting
+}
+
+// What to do with comments the formatter failed to print.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum CommentFallback {
+    #[default]
+    Fail, // report them as an error
+    Reattach, // insert them before their enclosing statement or member
+    Leave,    // keep the file unformatted
+}
+
+// Formatted code and the warnings raised while formatting it.
+struct Formatted {
+    code: String,
+    warnings: Vec<FormatWarning>,
+    cursor_offset: Option<usize>,
+    left_unformatted: bool, // `code` is the source, kept by `CommentFallback::Leave`
+}
+
 #[derive(Clone, Debug)]
 pub struct Formatter {
     config: Config,
     source_files: Vec<String>,
+    jobs: usize,
+    options: FormatOptions,
+    discover_config: bool,
+    config_dir: PathBuf, // `exclude` patterns of `config` are relative to it
+    configs: Arc<ConfigCache>,
+    cache: Option<Arc<Cache>>,
     //pub errors: ReportedErrors,
 }
 
@@ -79,146 +263,684 @@ impl Formatter {
         Self {
             config,
             source_files,
+            jobs: default_jobs(),
+            options: FormatOptions::default(),
+            discover_config: false,
+            config_dir: PathBuf::from("."),
+            configs: Arc::default(),
+            cache: None,
             //errors: ReportedErrors::default(),
         }
     }
 
+    // Set the number of worker threads used by `format()`.
+    pub fn with_jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs.max(1);
+        self
+    }
+
+    // Only format the statements or body members overlapping `range` in every file.
+    pub fn with_range(mut self, range: FormatRange) -> Self {
+        self.options.range = Some(range);
+        self
+    }
+
+    // Report where byte `offset` of the input ends up in the formatted code.
+    pub fn with_cursor_offset(mut self, offset: usize) -> Self {
+        self.options.cursor_offset = Some(offset);
+        self
+    }
+
+    // Re-parse every result and fail files whose tokens or comments changed.
+    pub fn with_verification(mut self) -> Self {
+        self.options.verify = true;
+        self
+    }
+
+    // Format every result a second time and fail files that change again.
+    pub fn with_idempotency_check(mut self) -> Self {
+        self.options.verify_idempotency = true;
+        self
+    }
+
+    // Skip files that the cache in `dir` knows to be formatted, and remember newly found ones.
+    pub fn with_cache(mut self, dir: &Path) -> Self {
+        self.cache = Some(Arc::new(Cache::load(dir)));
+        self
+    }
+
+    // Recover from comments the formatter failed to print instead of failing the file.
+    pub fn with_comment_fallback(mut self, fallback: CommentFallback) -> Self {
+        self.options.comment_fallback = fallback;
+        self
+    }
+
+    // Keep members that cannot be formatted as written and report them as warnings.
+    pub fn with_recovery(mut self) -> Self {
+        self.options.recover = true;
+        self
+    }
+
+    // Use the nearest `.afmt.toml` of each file, falling back to `config` when there is none.
+    pub fn with_config_discovery(mut self) -> Self {
+        self.discover_config = true;
+        self
+    }
+
+    pub fn with_source_files(mut self, source_files: Vec<String>) -> Self {
+        self.source_files = source_files;
+        self
+    }
+
+    // The config `file` is formatted with.
+    pub fn config_for(&self, file: &str) -> Result<Config, FormatError> {
+        self.resolve_config(file).map(|(config, _)| config)
+    }
+
+    // Whether `file` matches an `exclude` pattern of its config; invalid configs are reported
+    // when the file is formatted.
+    pub fn is_excluded(&self, file: &str) -> bool {
+        self.resolve_config(file)
+            .is_ok_and(|(config, config_dir)| config.excludes(Path::new(file), &config_dir))
+    }
+
+    // The config of `file` and the directory it was found in.
+    fn resolve_config(&self, file: &str) -> Result<(Config, PathBuf), FormatError> {
+        if self.discover_config {
+            let dir = match Path::new(file).parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+            if let Some(config_path) = Config::find_file(dir) {
+                let config = self.configs.load(&config_path)?;
+                let config_dir = config_path.parent().unwrap_or(Path::new("."));
+                return Ok((config, config_dir.to_path_buf()));
+            }
+        }
+        Ok((self.config.clone(), self.config_dir.clone()))
+    }
+
     pub fn config(&self) -> &Config {
         &self.config
     }
 
+    pub fn source_files(&self) -> &[String] {
+        &self.source_files
+    }
+
+    pub fn jobs(&self) -> usize {
+        self.jobs
+    }
+
+    pub fn options(&self) -> &FormatOptions {
+        &self.options
+    }
+
+    pub fn range(&self) -> Option<&FormatRange> {
+        self.options.range.as_ref()
+    }
+
+    // A formatter using the config file at `config_path` for every file, or the defaults when
+    // there is none; see `with_config_discovery()` for a config per file.
     pub fn create_from_config(
         config_path: Option<&str>,
         source_files: Vec<String>,
     ) -> Result<Formatter, String> {
-        let config = match config_path {
-            Some(path) => Config::from_file(path)
-                .map_err(|e| format!("{}: {}", yellow(&e.to_string()), path))?,
-            None => Config::default(),
+        let Some(path) = config_path else {
+            return Ok(Formatter::new(Config::default(), source_files));
         };
-        Ok(Formatter::new(config, source_files))
+
+        let config =
+            Config::from_file(path).map_err(|e| format!("{}: {}", yellow(&e.to_string()), path))?;
+        let mut formatter = Formatter::new(config, source_files);
+        if let Some(dir) = Path::new(path)
+            .parent()
+            .filter(|d| !d.as_os_str().is_empty())
+        {
+            formatter.config_dir = dir.to_path_buf();
+        }
+        Ok(formatter)
     }
 
-    pub fn format(&self) -> Vec<Result<String, String>> {
+    pub fn format(&self) -> Vec<FileOutcome> {
         let (tx, rx) = mpsc::channel();
-        let config = self.config.clone();
-
-        for file in &self.source_files {
-            let tx = tx.clone();
-            let config = config.clone();
-            let file = file.clone();
-
-            thread::spawn(move || {
-                let result = std::panic::catch_unwind(|| {
-                    let source_code = fs::read_to_string(Path::new(&file))
-                        .map_err(|e| {
-                            format!(
-                                "Failed to read file: {} {}",
-                                red(&file),
-                                yellow(e.to_string().as_str())
-                            )
-                        })
-                        .unwrap();
-
-                    Formatter::format_one(&source_code, config)
+        let next_index = AtomicUsize::new(0);
+        let workers = self.jobs.min(self.source_files.len()).max(1);
+
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                let tx = tx.clone();
+                let next_index = &next_index;
+
+                scope.spawn(move || {
+                    let mut worker = Worker::new();
+                    loop {
+                        let index = next_index.fetch_add(1, Ordering::Relaxed);
+                        let Some(file) = self.source_files.get(index) else {
+                            break;
+                        };
+
+                        // a bug in afmt panicking on one file must not take down the others
+                        let outcome =
+                            panic::catch_unwind(AssertUnwindSafe(|| match self.config_for(file) {
+                                Ok(config) => worker.format_file(
+                                    file,
+                                    &config,
+                                    &self.options,
+                                    self.cache.as_deref(),
+                                ),
+                                Err(e) => FileOutcome::failed(file, None, e),
+                            }))
+                            .unwrap_or_else(|_| {
+                                // the parser may be left mid-parse
+                                worker = Worker::new();
+                                let error = FormatError::Internal {
+                                    file: None,
+                                    message: "afmt panicked".to_string(),
+                                };
+                                FileOutcome::failed(file, None, error)
+                            });
+                        tx.send((index, outcome))
+                            .expect("failed to send outcome in tx");
+                    }
                 });
-                match result {
-                    Ok(result) => {
-                        tx.send(Ok(result)).expect("failed to send result in tx");
+            }
+        });
+
+        drop(tx);
+
+        if let Some(cache) = &self.cache {
+            // only an optimisation, the next run formats the files again
+            let _ = cache.save();
+        }
+
+        // keep outcomes in the same order as `source_files`
+        let mut outcomes: Vec<(usize, FileOutcome)> = rx.into_iter().collect();
+        outcomes.sort_by_key(|(index, _)| *index);
+        outcomes.into_iter().map(|(_, outcome)| outcome).collect()
+    }
+
+    pub fn format_one(source_code: &str, config: Config) -> Result<String, FormatError> {
+        let options = FormatOptions::default();
+        Self::format_with_parser(&mut Self::new_parser(), source_code, config, &options)
+            .map(|formatted| formatted.code)
+    }
+
+    // Format twice and fail with the diff between both passes if the second one changes anything.
+    pub fn format_idempotent(source_code: &str, config: Config) -> Result<String, FormatError> {
+        let options = FormatOptions {
+            verify_idempotency: true,
+            ..FormatOptions::default()
+        };
+        Self::format_with_parser(&mut Self::new_parser(), source_code, config, &options)
+            .map(|formatted| formatted.code)
+    }
+
+    // Format only the statements or body members overlapping `range`.
+    pub fn format_range(
+        source_code: &str,
+        config: Config,
+        range: &FormatRange,
+    ) -> Result<String, FormatError> {
+        let options = FormatOptions {
+            range: Some(range.clone()),
+            ..FormatOptions::default()
+        };
+        Self::format_with_parser(&mut Self::new_parser(), source_code, config, &options)
+            .map(|formatted| formatted.code)
+    }
+
+    // Format the code and return it with the offset matching `cursor_offset` in it, so an editor
+    // can keep the caret on the same token. The offset is None when it cannot be mapped.
+    pub fn format_with_cursor(
+        source_code: &str,
+        config: Config,
+        cursor_offset: usize,
+    ) -> Result<(String, Option<usize>), FormatError> {
+        let options = FormatOptions {
+            cursor_offset: Some(cursor_offset),
+            ..FormatOptions::default()
+        };
+        Self::format_with_parser(&mut Self::new_parser(), source_code, config, &options)
+            .map(|formatted| (formatted.code, formatted.cursor_offset))
+    }
+
+    // Format source code that was not read from `path`, e.g. an editor buffer from stdin.
+    pub fn format_source(
+        path: &str,
+        source_code: String,
+        config: Config,
+        options: &FormatOptions,
+    ) -> FileOutcome {
+        let start = Instant::now();
+        let result =
+            Self::format_with_parser(&mut Self::new_parser(), &source_code, config, options);
+        let mut outcome = FileOutcome::new(path, source_code, result);
+        outcome.duration = start.elapsed();
+        outcome
+    }
+
+    fn format_with_parser(
+        parser: &mut Parser,
+        source_code: &str,
+        config: Config,
+        options: &FormatOptions,
+    ) -> Result<Formatted, FormatError> {
+        let (source_tree, mut formatted, second) = match &options.range {
+            // the rest of the file may not even parse, so it is left alone
+            Some(range) => {
+                let source_tree = Self::parse_with_parser(parser, source_code, true)?;
+                let range = range.to_bytes(source_code);
+                let (formatted, piece) = Self::format_range_of_tree(
+                    parser,
+                    &source_tree,
+                    source_code,
+                    &config,
+                    options,
+                    range,
+                )?;
+                let second = match options.verify_idempotency {
+                    true => {
+                        let tree = Self::parse_with_parser(parser, &formatted.code, true)?;
+                        let code = &formatted.code;
+                        Some(
+                            Self::format_range_of_tree(
+                                parser, &tree, code, &config, options, piece,
+                            )?
+                            .0,
+                        )
+                    }
+                    false => None,
+                };
+                (source_tree, formatted, second)
+            }
+            None => {
+                let source_tree = Self::parse_with_parser(parser, source_code, options.recover)?;
+                let formatted =
+                    Self::format_tree(parser, &source_tree, source_code, config.clone(), options)?;
+                let second = match options.verify_idempotency {
+                    true => {
+                        let tree =
+                            Self::parse_with_parser(parser, &formatted.code, options.recover)?;
+                        Some(Self::format_tree(
+                            parser,
+                            &tree,
+                            &formatted.code,
+                            config,
+                            options,
+                        )?)
                     }
-                    Err(_) => tx
-                        .send(Err("Thread panicked".to_string()))
-                        .expect("failed to send error in tx"),
-                }
+                    false => None,
+                };
+                (source_tree, formatted, second)
+            }
+        };
+
+        if let Some(second) = second.filter(|second| second.code != formatted.code) {
+            return Err(FormatError::Unstable {
+                file: None,
+                diff: report::diff_hunks(&formatted.code, &second.code, 3),
             });
         }
 
-        drop(tx);
+        if options.verify {
+            let formatted_tree = Self::parse_with_parser(parser, &formatted.code, true)?;
+            verify::verify(source_code, &source_tree, &formatted.code, &formatted_tree)?;
+        }
 
-        rx.into_iter().collect()
+        if let Some(offset) = options.cursor_offset {
+            let formatted_tree = Self::parse_with_parser(parser, &formatted.code, true)?;
+            formatted.cursor_offset = cursor::map_offset(
+                source_code,
+                &source_tree,
+                &formatted.code,
+                &formatted_tree,
+                offset,
+            );
+        }
+        Ok(formatted)
     }
 
-    pub fn format_one(source_code: &str, config: Config) -> String {
-        let ast_tree = Formatter::parse(source_code);
-        set_thread_source_code(source_code.to_string()); // important to set thread level source code now;
+    // Format the statements or body members overlapping `range` and keep the rest of the source as
+    // written. Also returns the byte range of the formatted part in the result.
+    fn format_range_of_tree(
+        parser: &mut Parser,
+        ast_tree: &Tree,
+        source_code: &str,
+        config: &Config,
+        options: &FormatOptions,
+        range: Range<usize>,
+    ) -> Result<(Formatted, Range<usize>), FormatError> {
+        let mut formatted = Formatted {
+            code: source_code.to_string(),
+            warnings: Vec::new(),
+            cursor_offset: None,
+            left_unformatted: false,
+        };
+        let Some(units) = range::enclosing_units(ast_tree.root_node(), range.clone()) else {
+            return Ok((formatted, range.start..range.start));
+        };
+        if !options.recover && units.iter().any(|unit| unit.has_error()) {
+            let mut diagnostics = Vec::new();
+            for unit in &units {
+                Self::collect_syntax_diagnostics(unit, source_code, &mut diagnostics);
+            }
+            return Err(FormatError::Syntax(diagnostics));
+        }
 
-        let mut cursor = ast_tree.walk();
-        let mut comment_map = CommentMap::new();
-        collect_comments(&mut cursor, &mut comment_map);
-        set_thread_comment_map(comment_map); // important to set thread level comment map;
+        let _context = FormatContext::new(source_code, ast_tree, options.recover).enter();
+        let b = DocBuilder::new(PrettyConfig::new(config.indent_size));
+        let printed = pretty_print(range::build_units(&b, &units)?, config.max_width);
+
+        // the comments printed with the units are replaced as well
+        let last = units[units.len() - 1];
+        let (start, end) = printed_comments().into_iter().fold(
+            (units[0].start_byte(), last.end_byte()),
+            |(start, end), comment| (start.min(comment.start), end.max(comment.end)),
+        );
+        // the indentation is only printed when the units start their line
+        let line_start = range::extend_to_line_start(source_code, start);
+        let piece = match line_start < start || source_code[..start].ends_with('\n') || start == 0 {
+            true => printed.trim_start_matches('\n').trim_end(),
+            false => printed.trim(),
+        };
+        formatted.code = format!(
+            "{}{}{}",
+            &source_code[..line_start],
+            piece,
+            &source_code[end..]
+        );
+        formatted.warnings = FormatContext::current().take_warnings();
+        let piece_range = line_start..line_start + piece.len();
+
+        let erased: Vec<Range<usize>> = erased_comments()
+            .into_iter()
+            .filter(|comment| line_start <= comment.start && comment.end <= end)
+            .collect();
+        drop(_context);
+        if !erased.is_empty() {
+            formatted = Self::fall_back_on_erased_comments(
+                parser,
+                ast_tree,
+                source_code,
+                formatted,
+                &erased,
+                options,
+            )?;
+        }
+        Ok((formatted, piece_range))
+    }
 
-        // traverse the tree to build enriched data
-        let root: Root = enrich(&ast_tree);
+    // Pretty print the tree, then deal with the comments it erased.
+    fn format_tree(
+        parser: &mut Parser,
+        ast_tree: &Tree,
+        source_code: &str,
+        config: Config,
+        options: &FormatOptions,
+    ) -> Result<Formatted, FormatError> {
+        let (mut formatted, erased) =
+            Self::pretty_print_tree(ast_tree, source_code, config, options.recover)?;
+        if !erased.is_empty() {
+            formatted = Self::fall_back_on_erased_comments(
+                parser,
+                ast_tree,
+                source_code,
+                formatted,
+                &erased,
+                options,
+            )?;
+        }
+        Ok(formatted)
+    }
 
-        // traverse enriched data and create pretty print combinators
-        let c = PrettyConfig::new(config.indent_size);
-        let b = DocBuilder::new(c);
-        let doc_ref = root.build(&b);
+    fn fall_back_on_erased_comments(
+        parser: &mut Parser,
+        ast_tree: &Tree,
+        source_code: &str,
+        mut formatted: Formatted,
+        erased: &[Range<usize>],
+        options: &FormatOptions,
+    ) -> Result<Formatted, FormatError> {
+        let error = || {
+            let locations = erased
+                .iter()
+                .map(|range| {
+                    ErrorLocation::at(source_code, range.start, &source_code[range.clone()])
+                })
+                .collect();
+            FormatError::ErasedComments(locations)
+        };
+        let warnings = |message: &str| -> Vec<FormatWarning> {
+            erased
+                .iter()
+                .map(|range| {
+                    let text = &source_code[range.clone()];
+                    FormatWarning {
+                        message: message.to_string(),
+                        location: ErrorLocation::at(
+                            source_code,
+                            range.start,
+                            text.lines().next().unwrap_or_default(),
+                        ),
+                    }
+                })
+                .collect()
+        };
 
-        let result = pretty_print(doc_ref, config.max_width);
+        match options.comment_fallback {
+            CommentFallback::Fail => Err(error()),
+            CommentFallback::Leave => Ok(Formatted {
+                code: source_code.to_string(),
+                warnings: warnings("comment would be erased, file left unformatted"),
+                cursor_offset: None,
+                left_unformatted: true,
+            }),
+            CommentFallback::Reattach => {
+                let formatted_tree =
+                    Self::parse_with_parser(parser, &formatted.code, options.recover)?;
+                formatted.code = orphans::reattach(
+                    source_code,
+                    ast_tree,
+                    &formatted.code,
+                    &formatted_tree,
+                    erased,
+                )
+                .ok_or_else(error)?;
+                formatted.warnings.extend(warnings(
+                    "comment moved before its enclosing statement or member",
+                ));
+                formatted
+                    .warnings
+                    .sort_by_key(|w| (w.location.line, w.location.column));
+                Ok(formatted)
+            }
+        }
+    }
 
-        // debugging tool: use this to print named node value + comments in bucket
-        // print_comment_map(&ast_tree);
+    // The formatted code and the byte ranges of the comments it is missing.
+    fn pretty_print_tree(
+        ast_tree: &Tree,
+        source_code: &str,
+        config: Config,
+        recover: bool,
+    ) -> Result<(Formatted, Vec<Range<usize>>), FormatError> {
+        // source code and comments stay reachable until `_context` is dropped;
+        let _context = FormatContext::new(source_code, ast_tree, recover).enter();
 
-        assert_no_missing_comments();
+        // traverse the tree to build enriched data
+        let result = enrich(ast_tree).map(|root: Root| {
+            // traverse enriched data and create pretty print combinators
+            let c = PrettyConfig::new(config.indent_size);
+            let b = DocBuilder::new(c);
+            let doc_ref = root.build(&b);
+
+            // debugging tool: use this to print named node value + comments in bucket
+            // print_comment_map(ast_tree);
+
+            pretty_print(doc_ref, config.max_width)
+        });
+
+        // syntax errors outside of the members kept as written come first
+        let context = FormatContext::current();
+        if context.has_unrecovered_errors() {
+            return Err(Self::syntax_error(ast_tree, source_code));
+        }
 
-        result
+        let code = result?;
+        let formatted = Formatted {
+            code,
+            warnings: context.take_warnings(),
+            cursor_offset: None,
+            left_unformatted: false,
+        };
+        Ok((formatted, erased_comments()))
     }
 
-    pub fn parse(source_code: &str) -> Tree {
+    fn new_parser() -> Parser {
         let mut parser = Parser::new();
         let language_fn = tree_sitter_sfapex::apex::LANGUAGE;
         parser
             .set_language(&language_fn.into())
             .expect("Error loading Apex parser");
+        parser
+    }
+
+    pub fn parse(source_code: &str) -> Result<Tree, FormatError> {
+        Self::parse_with_parser(&mut Self::new_parser(), source_code, false)
+    }
 
+    // With `recover`, trees with syntax errors are returned for the data model to work around.
+    fn parse_with_parser(
+        parser: &mut Parser,
+        source_code: &str,
+        recover: bool,
+    ) -> Result<Tree, FormatError> {
         let ast_tree = parser.parse(source_code, None).unwrap();
-        let root_node = &ast_tree.root_node();
-
-        if root_node.has_error() {
-            if let Some(error_node) = Self::find_last_error_node(root_node) {
-                let error_snippet =
-                    truncate_snippet(&source_code[error_node.start_byte()..error_node.end_byte()]);
-                println!(
-                    "Error in node kind: {}, at byte range: {}-{}, snippet: {}",
-                    yellow(error_node.kind()),
-                    error_node.start_byte(),
-                    error_node.end_byte(),
-                    error_snippet,
-                );
-                if let Some(p) = error_node.parent() {
-                    let parent_snippet =
-                        truncate_snippet(&source_code[p.start_byte()..p.end_byte()]);
-                    println!(
-                        "Parent node kind: {}, at byte range: {}-{}, snippet: {}",
-                        yellow(p.kind()),
-                        p.start_byte(),
-                        p.end_byte(),
-                        parent_snippet,
-                    );
-                }
+
+        if ast_tree.root_node().has_error() && !recover {
+            return Err(Self::syntax_error(&ast_tree, source_code));
+        }
+
+        Ok(ast_tree)
+    }
+
+    fn syntax_error(ast_tree: &Tree, source_code: &str) -> FormatError {
+        let mut diagnostics = Vec::new();
+        Self::collect_syntax_diagnostics(&ast_tree.root_node(), source_code, &mut diagnostics);
+        FormatError::Syntax(diagnostics)
+    }
+
+    // Collect every ERROR and MISSING node in source order; nodes inside an ERROR node are skipped.
+    fn collect_syntax_diagnostics(
+        node: &Node,
+        source_code: &str,
+        diagnostics: &mut Vec<SyntaxDiagnostic>,
+    ) {
+        if node.is_error() || node.is_missing() {
+            diagnostics.push(SyntaxDiagnostic::from_node(node, source_code));
+            return;
+        }
+
+        let mut cursor = node.walk();
+        for child in node.children(&mut cursor) {
+            if child.has_error() {
+                Self::collect_syntax_diagnostics(&child, source_code, diagnostics);
             }
-            panic!("{}", red("Parser encounters an error node in the tree."));
         }
+    }
+}
+
+// Configs found by discovery, loaded once and shared by all workers.
+#[derive(Debug, Default)]
+struct ConfigCache {
+    loaded: Mutex<HashMap<PathBuf, Result<Config, FormatError>>>,
+}
 
-        ast_tree
+impl ConfigCache {
+    fn load(&self, config_path: &Path) -> Result<Config, FormatError> {
+        let mut loaded = self.loaded.lock().expect("config cache lock poisoned");
+        loaded
+            .entry(config_path.to_path_buf())
+            .or_insert_with(|| {
+                let path = config_path.to_string_lossy().to_string();
+                Config::from_file(&path).map_err(|message| FormatError::Config {
+                    file: path,
+                    message,
+                })
+            })
+            .clone()
     }
+}
+
+fn default_jobs() -> usize {
+    thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+}
+
+// State owned by one worker thread and reused for every file it formats.
+struct Worker {
+    parser: Parser,
+}
 
-    fn find_last_error_node<'tree>(node: &Node<'tree>) -> Option<Node<'tree>> {
-        if !node.has_error() {
-            return None; // If the current node has no error, return None
+impl Worker {
+    fn new() -> Self {
+        Self {
+            parser: Formatter::new_parser(),
         }
+    }
+
+    fn format_file(
+        &mut self,
+        file: &str,
+        config: &Config,
+        options: &FormatOptions,
+        cache: Option<&Cache>,
+    ) -> FileOutcome {
+        let start = Instant::now();
+        let mut outcome = self.format_file_inner(file, config, options, cache);
+        outcome.duration = start.elapsed();
+        outcome
+    }
 
-        let mut last_error_node = Some(*node);
+    fn format_file_inner(
+        &mut self,
+        file: &str,
+        config: &Config,
+        options: &FormatOptions,
+        cache: Option<&Cache>,
+    ) -> FileOutcome {
+        let source_code = match fs::read_to_string(Path::new(file)) {
+            Ok(source_code) => source_code,
+            Err(e) => {
+                let error = FormatError::Io {
+                    file: file.to_string(),
+                    message: format!("Failed to read file: {}", e),
+                };
+                return FileOutcome::failed(file, None, error);
+            }
+        };
 
-        for i in 0..node.child_count() {
-            if let Some(child) = node.child(i) {
-                if child.has_error() {
-                    last_error_node = Self::find_last_error_node(&child);
-                }
+        let cached = cache.map(|cache| (cache, cache.key(&source_code, config, options)));
+        if let Some((cache, key)) = cached {
+            if cache.is_formatted(key) {
+                let cursor_offset = options.cursor_offset.map(|o| o.min(source_code.len()));
+                let mut outcome = FileOutcome::formatted(file, source_code.clone(), source_code);
+                outcome.cursor_offset = cursor_offset;
+                return outcome;
             }
         }
 
-        last_error_node // Return the last (deepest) error node
+        let result =
+            Formatter::format_with_parser(&mut self.parser, &source_code, config.clone(), options);
+        let outcome = FileOutcome::new(file, source_code, result);
+
+        if let Some((cache, key)) = cached {
+            if !outcome.changed && !outcome.is_error() && outcome.warnings.is_empty() {
+                cache.insert(key);
+            }
+        }
+        outcome
     }
 }
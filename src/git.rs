@@ -0,0 +1,107 @@
+use crate::files::is_source_file;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+// Source files in the working tree that differ from the merge base of `reference` and HEAD,
+// i.e. what a branch based on `reference` touches. `paths` limit the search like pathspecs.
+pub fn changed_since(reference: &str, paths: &[String]) -> Result<Vec<String>, String> {
+    let mut args = vec![
+        "diff",
+        "--name-only",
+        "-z",
+        "--relative",
+        "--diff-filter=ACMR",
+        "--merge-base",
+        reference,
+        "--",
+    ];
+    args.extend(paths.iter().map(|p| p.as_str()));
+    Ok(source_files(&git(&args, None)?))
+}
+
+// Source files with staged changes.
+pub fn staged(paths: &[String]) -> Result<Vec<String>, String> {
+    let mut args = vec![
+        "diff",
+        "--cached",
+        "--name-only",
+        "-z",
+        "--relative",
+        "--diff-filter=ACMR",
+        "--",
+    ];
+    args.extend(paths.iter().map(|p| p.as_str()));
+    Ok(source_files(&git(&args, None)?))
+}
+
+// The staged content of `path`.
+pub fn read_staged(path: &str) -> Result<String, String> {
+    git(&["show", &format!(":./{}", path)], None)
+}
+
+// Stage `content` as the new content of `path`. The working tree file is only updated when it
+// has no unstaged changes, so those are kept when a file is partially staged.
+pub fn restage(path: &str, staged: &str, content: &str) -> Result<(), String> {
+    let fully_staged = std::fs::read_to_string(path).is_ok_and(|current| current == staged);
+
+    // `<mode> <blob> <stage>\t<path>`, the path relative to the repository root as
+    // `--cacheinfo` expects it; `path` is relative to the current directory
+    let entry = git(
+        &["ls-files", "--stage", "--full-name", "-z", "--", path],
+        None,
+    )?;
+    let (mode, full_name) = entry
+        .split_once('\t')
+        .and_then(|(info, name)| Some((info.split_whitespace().next()?, name)))
+        .ok_or_else(|| format!("{} is not in the git index", path))?;
+    let full_name = full_name.trim_end_matches('\0');
+    let blob = git(&["hash-object", "-w", "--stdin"], Some(content))?;
+    let cache_info = format!("{},{},{}", mode, blob.trim(), full_name);
+    git(&["update-index", "--cacheinfo", &cache_info], None)?;
+
+    if fully_staged {
+        std::fs::write(path, content)
+            .map_err(|e| format!("Failed to write formatted content to {}: {}", path, e))?;
+    }
+    Ok(())
+}
+
+fn source_files(output: &str) -> Vec<String> {
+    output
+        .split('\0')
+        .filter(|path| is_source_file(Path::new(path)))
+        .map(|path| path.to_string())
+        .collect()
+}
+
+fn git(args: &[&str], stdin: Option<&str>) -> Result<String, String> {
+    let mut child = Command::new("git")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run git: {}", e))?;
+
+    if let Some(input) = stdin {
+        child
+            .stdin
+            .take()
+            .expect("stdin is piped")
+            .write_all(input.as_bytes())
+            .map_err(|e| format!("Failed to write to git: {}", e))?;
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("Failed to run git: {}", e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "`git {}` failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    String::from_utf8(output.stdout).map_err(|e| format!("git output is not UTF-8: {}", e))
+}
@@ -1,31 +1,33 @@
+#![allow(clippy::large_enum_variant)]
+
 mod accessor;
 pub mod args;
+pub mod cache;
 mod context;
+mod cursor;
 mod data_model;
 mod doc;
 mod doc_builder;
+pub mod edits;
 mod enum_def;
+pub mod error;
+pub mod ffi;
+pub mod files;
 pub mod formatter;
+pub mod git;
+#[cfg(feature = "lsp")]
+pub mod lsp;
 pub mod message_helper;
+mod orphans;
+pub mod range;
+pub mod report;
+mod suppression;
 mod utility;
-use formatter::Formatter;
+mod verify;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+use formatter::{FileOutcome, Formatter};
 
-pub fn format(f: Formatter) -> Vec<Result<String, String>> {
+pub fn format(f: Formatter) -> Vec<FileOutcome> {
     f.format()
 }
-
-//#[wasm_bindgen]
-//pub fn greet(source_code: &str) -> String {
-//    let config = Config::default();
-//    Formatter::format_one(source_code, config)
-//}
-
-//#[wasm_bindgen]
-//pub fn greet(source_code: &str) -> String {
-//    "hello".to_string()
-//}
-
-//#[wasm_bindgen]
-//pub fn greet() -> Result<String, JsValue> {
-//    Ok("hello world!".to_string())
-//}
@@ -0,0 +1,255 @@
+use crate::edits;
+use crate::error::FormatError;
+use crate::formatter::{Config, FormatOptions, Formatter};
+use crate::range::FormatRange;
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+    Notification as LspNotification, PublishDiagnostics,
+};
+use lsp_types::request::{Formatting, OnTypeFormatting, RangeFormatting, Request as LspRequest};
+use lsp_types::{
+    Diagnostic, DiagnosticSeverity, DocumentFormattingParams, DocumentOnTypeFormattingOptions,
+    DocumentOnTypeFormattingParams, DocumentRangeFormattingParams, OneOf, Position,
+    PublishDiagnosticsParams, Range, ServerCapabilities, TextDocumentSyncCapability,
+    TextDocumentSyncKind, Url,
+};
+use std::collections::HashMap;
+use std::error::Error;
+
+// Characters that format the statement or member they end while typing.
+const ON_TYPE_TRIGGERS: [&str; 2] = [";", "}"];
+
+// Serve LSP requests on `connection` until the client shuts the server down.
+pub fn run(connection: Connection) -> Result<(), Box<dyn Error + Sync + Send>> {
+    let capabilities = serde_json::to_value(capabilities())?;
+    connection.initialize(capabilities)?;
+
+    let mut server = Server::new();
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    return Ok(());
+                }
+                let response = server.handle_request(request);
+                connection.sender.send(Message::Response(response))?;
+            }
+            Message::Notification(notification) => {
+                if let Some(diagnostics) = server.handle_notification(notification) {
+                    connection.sender.send(Message::Notification(diagnostics))?;
+                }
+            }
+            Message::Response(_) => {}
+        }
+    }
+    Ok(())
+}
+
+fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        document_range_formatting_provider: Some(OneOf::Left(true)),
+        document_on_type_formatting_provider: Some(DocumentOnTypeFormattingOptions {
+            first_trigger_character: ON_TYPE_TRIGGERS[0].to_string(),
+            more_trigger_character: Some(
+                ON_TYPE_TRIGGERS[1..]
+                    .iter()
+                    .map(|c| c.to_string())
+                    .collect(),
+            ),
+        }),
+        ..ServerCapabilities::default()
+    }
+}
+
+// An open document, as last sent by the client.
+struct Document {
+    text: String,
+    version: i32,
+}
+
+struct Server {
+    documents: HashMap<Url, Document>,
+    formatter: Formatter, // resolves the `.afmt.toml` of each document
+}
+
+impl Server {
+    fn new() -> Self {
+        Self {
+            documents: HashMap::new(),
+            formatter: Formatter::new(Config::default(), Vec::new()).with_config_discovery(),
+        }
+    }
+
+    fn handle_request(&mut self, request: Request) -> Response {
+        let id = request.id.clone();
+        let result = match request.method.as_str() {
+            Formatting::METHOD => params::<DocumentFormattingParams>(request)
+                .map(|p| self.format(&p.text_document.uri, None)),
+            RangeFormatting::METHOD => params::<DocumentRangeFormattingParams>(request).map(|p| {
+                let range =
+                    self.documents
+                        .get(&p.text_document.uri)
+                        .map(|document| FormatRange::Bytes {
+                            start: edits::offset(&document.text, edits_position(p.range.start)),
+                            end: edits::offset(&document.text, edits_position(p.range.end)),
+                        });
+                range.and_then(|range| self.format(&p.text_document.uri, Some(range)))
+            }),
+            OnTypeFormatting::METHOD => {
+                params::<DocumentOnTypeFormattingParams>(request).map(|p| {
+                    // the statement or member ending on the typed line
+                    let position = p.text_document_position;
+                    let line = position.position.line as usize + 1;
+                    let range = FormatRange::Lines {
+                        first: line,
+                        last: line,
+                    };
+                    self.format(&position.text_document.uri, Some(range))
+                })
+            }
+            method => Err((
+                ErrorCode::MethodNotFound,
+                format!("unsupported method: {}", method),
+            )),
+        };
+
+        match result {
+            Ok(edits) => Response::new_ok(id, edits),
+            Err((code, message)) => Response::new_err(id, code as i32, message),
+        }
+    }
+
+    // Update the document state; returns the diagnostics to publish for it.
+    fn handle_notification(&mut self, notification: Notification) -> Option<Notification> {
+        let uri = match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let p: lsp_types::DidOpenTextDocumentParams =
+                    serde_json::from_value(notification.params).ok()?;
+                let document = Document {
+                    text: p.text_document.text,
+                    version: p.text_document.version,
+                };
+                self.documents.insert(p.text_document.uri.clone(), document);
+                p.text_document.uri
+            }
+            DidChangeTextDocument::METHOD => {
+                let p: lsp_types::DidChangeTextDocumentParams =
+                    serde_json::from_value(notification.params).ok()?;
+                // full sync: the last change holds the whole text
+                let text = p.content_changes.into_iter().last()?.text;
+                let document = Document {
+                    text,
+                    version: p.text_document.version,
+                };
+                self.documents.insert(p.text_document.uri.clone(), document);
+                p.text_document.uri
+            }
+            DidCloseTextDocument::METHOD => {
+                let p: lsp_types::DidCloseTextDocumentParams =
+                    serde_json::from_value(notification.params).ok()?;
+                self.documents.remove(&p.text_document.uri);
+                return Some(publish_diagnostics(p.text_document.uri, Vec::new(), None));
+            }
+            _ => return None,
+        };
+
+        let document = &self.documents[&uri];
+        let diagnostics = diagnostics(&document.text);
+        Some(publish_diagnostics(
+            uri,
+            diagnostics,
+            Some(document.version),
+        ))
+    }
+
+    // `None` when the document is unknown or cannot be formatted, e.g. while it has syntax errors;
+    // with a range, only those in the statements or members formatted count.
+    // The style comes from `.afmt.toml`, not from the client's formatting options.
+    fn format(&self, uri: &Url, range: Option<FormatRange>) -> Option<Vec<edits::TextEdit>> {
+        let document = self.documents.get(uri)?;
+        let path = uri
+            .to_file_path()
+            .map(|path| path.to_string_lossy().to_string())
+            .unwrap_or_else(|_| uri.to_string());
+        let config = self.formatter.config_for(&path).ok()?;
+        let options = FormatOptions {
+            range,
+            ..self.formatter.options().clone()
+        };
+
+        let outcome = Formatter::format_source(&path, document.text.clone(), config, &options);
+        let formatted = outcome.formatted?;
+        Some(edits::text_edits(&document.text, &formatted))
+    }
+}
+
+fn params<P: serde::de::DeserializeOwned>(request: Request) -> Result<P, (ErrorCode, String)> {
+    serde_json::from_value(request.params)
+        .map_err(|e| (ErrorCode::InvalidParams, format!("invalid params: {}", e)))
+}
+
+fn publish_diagnostics(
+    uri: Url,
+    diagnostics: Vec<Diagnostic>,
+    version: Option<i32>,
+) -> Notification {
+    let params = PublishDiagnosticsParams {
+        uri,
+        diagnostics,
+        version,
+    };
+    Notification::new(PublishDiagnostics::METHOD.to_string(), params)
+}
+
+// The syntax errors of `text`, as reported by the CLI.
+fn diagnostics(text: &str) -> Vec<Diagnostic> {
+    let Err(FormatError::Syntax(errors)) = Formatter::parse(text) else {
+        return Vec::new();
+    };
+
+    errors
+        .iter()
+        .map(|error| {
+            let line = edits::Position {
+                line: error.location.line as u32 - 1,
+                character: 0,
+            };
+            let line_start = edits::offset(text, line);
+            let start: usize = text[line_start..]
+                .chars()
+                .take(error.location.column - 1)
+                .map(char::len_utf8)
+                .sum();
+            let end: usize = text[line_start + start..]
+                .chars()
+                .take(error.underline_width)
+                .map(char::len_utf8)
+                .sum();
+            let range = Range::new(
+                lsp_position(edits::position(text, line_start + start)),
+                lsp_position(edits::position(text, line_start + start + end)),
+            );
+            Diagnostic {
+                range,
+                severity: Some(DiagnosticSeverity::ERROR),
+                source: Some("afmt".to_string()),
+                message: error.message.clone(),
+                ..Diagnostic::default()
+            }
+        })
+        .collect()
+}
+
+fn lsp_position(position: edits::Position) -> Position {
+    Position::new(position.line, position.character)
+}
+
+fn edits_position(position: Position) -> edits::Position {
+    edits::Position {
+        line: position.line,
+        character: position.character,
+    }
+}
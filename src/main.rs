@@ -1,6 +1,15 @@
-use sf_afmt::args::{get_args, Args};
+use sf_afmt::args::{get_args, Args, MessageFormat};
+use sf_afmt::cache::Cache;
+use sf_afmt::error::FormatError;
+use sf_afmt::files::{collect_source_files, filter_ignored};
 use sf_afmt::format;
-use sf_afmt::formatter::Formatter;
+use sf_afmt::formatter::{Config, FileOutcome, Formatter};
+use sf_afmt::git;
+use sf_afmt::report::{
+    unified_diff, FileRecord, EXIT_FORMAT_ERROR, EXIT_IO_ERROR, EXIT_NEEDS_FORMATTING,
+};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
 use std::time::Instant;
 use std::{fs, process};
 
@@ -11,54 +20,272 @@ fn main() {
     let result = run(&args);
 
     match result {
-        Ok(_) => {
+        Ok(code) => {
             if args.time {
                 let duration = start.elapsed();
-                println!("\n-- Execution time: {:?}", duration);
+                // Keep stdout machine-readable in json mode.
+                match args.message_format {
+                    MessageFormat::Json => eprintln!("-- Execution time: {:?}", duration),
+                    MessageFormat::Human => println!("\n-- Execution time: {:?}", duration),
+                }
             }
-            process::exit(0);
+            process::exit(code);
         }
         Err(e) => {
-            eprint!("Error: {}", e);
-            process::exit(1);
+            eprintln!("Error: {}", e);
+            process::exit(EXIT_IO_ERROR);
         }
     }
 }
 
-fn run(args: &Args) -> Result<(), String> {
-    let formatter = Formatter::create_from_config(args.config.as_deref(), vec![args.path.clone()])?;
-    let results = format(formatter);
-
-    for (index, result) in results.iter().enumerate() {
-        match result {
-            Ok(value) => {
-                if args.check {
-                    let original_content = fs::read_to_string(&args.path)
-                        .map_err(|e| format!("Failed to read file {}: {}", args.path, e))?;
-
-                    if original_content.as_str() == value {
-                        println!("File is already formatted: {}", args.path);
-                        return Ok(());
-                    } else {
-                        eprintln!("File is not correctly formatted: {}", args.path);
-                        return Err("Formatting check failed".to_string());
-                    }
-                } else if args.write {
-                    fs::write(&args.path, value).map_err(|e| {
-                        format!("Failed to write formatted content to {}: {}", args.path, e)
-                    })?;
-                    println!("Formatted content written back to: {}\n", args.path);
-                } else {
-                    //println!("Result {}: Ok\n{}", index, value);
-                    println!("{}", value);
+// The exit code once all files are processed, or an error that stopped the run.
+fn run(args: &Args) -> Result<i32, String> {
+    let outcomes = if args.stdin {
+        vec![format_stdin(args)?]
+    } else if args.staged {
+        format_staged(args)?
+    } else {
+        let formatter = create_formatter(args, Vec::new())?;
+        let source_files = match &args.changed_since {
+            Some(reference) => {
+                filter_ignored(git::changed_since(reference, &args.paths)?, |file| {
+                    formatter.is_excluded(file)
+                })
+            }
+            None => collect_source_files(&args.paths, args.include_ignored, |file| {
+                formatter.is_excluded(file)
+            })?,
+        };
+        if source_files.is_empty() {
+            // e.g. a git hook passing only ignored files
+            let only_files = args.paths.iter().all(|path| Path::new(path).is_file());
+            if args.changed_since.is_some() || only_files {
+                return Ok(0);
+            }
+            return Err("No .cls or .trigger files found".to_string());
+        }
+
+        let mut formatter = formatter.with_source_files(source_files);
+        if let Some(jobs) = args.jobs {
+            formatter = formatter.with_jobs(jobs);
+        }
+        let cache_dir = args
+            .cache_dir
+            .as_ref()
+            .map(PathBuf::from)
+            .or_else(Cache::default_dir);
+        if let Some(dir) = cache_dir.filter(|_| !args.no_cache) {
+            formatter = formatter.with_cache(&dir);
+        }
+        if formatter.range().is_some() && formatter.source_files().len() > 1 {
+            return Err("A range can only be formatted in a single file".to_string());
+        }
+        if args.cursor_offset.is_some() && formatter.source_files().len() > 1 {
+            return Err("A cursor offset can only be used with a single file".to_string());
+        }
+        format(formatter)
+    };
+
+    let json = args.message_format == MessageFormat::Json;
+    let writes = args.write && !args.diff && !args.check;
+    let mut unformatted = 0;
+    let mut failed = 0;
+    let mut failure_code = EXIT_FORMAT_ERROR;
+    for mut outcome in outcomes {
+        // a file that cannot be written fails on its own, the others are still written
+        if writes && outcome.changed {
+            if let Err(e) = write_file(args, &outcome) {
+                outcome.error = Some(e);
+            }
+        }
+        let outcome = &outcome;
+        let path = &outcome.path;
+
+        if json {
+            println!("{}", FileRecord::new(outcome).to_json());
+        }
+
+        if !json {
+            for warning in &outcome.warnings {
+                eprintln!("Warning: {}", warning);
+            }
+        }
+
+        if let Some(ref e) = outcome.error {
+            failed += 1;
+            if matches!(e, FormatError::Io { .. } | FormatError::Config { .. }) {
+                failure_code = EXIT_IO_ERROR;
+            }
+            if !json {
+                match e {
+                    FormatError::Syntax(_) => eprintln!("{}\n", e),
+                    _ => eprintln!("Error: {}", e),
                 }
             }
-            Err(e) => {
-                //println!("Result {}: Err\n{}", index, e);
-                return Err(format!("Error processing result {}: {}", index, e));
+            continue;
+        }
+
+        let formatted = outcome
+            .formatted
+            .as_deref()
+            .expect("formatted content must exist without error");
+
+        if args.diff {
+            if outcome.left_unformatted {
+                unformatted += 1;
+            } else if outcome.changed {
+                unformatted += 1;
+                if !json {
+                    let original = outcome.original.as_deref().unwrap_or_default();
+                    print!(
+                        "{}",
+                        unified_diff(path, original, formatted, args.diff_context)
+                    );
+                }
+            }
+        } else if args.check {
+            if outcome.needs_formatting() {
+                unformatted += 1;
+                if !json {
+                    eprintln!("File is not correctly formatted: {}", path);
+                }
+            } else if !json {
+                println!("File is already formatted: {}", path);
+            }
+        } else if args.list_different {
+            if outcome.needs_formatting() {
+                if !(args.write && outcome.changed) {
+                    unformatted += 1;
+                }
+                if !json {
+                    println!("{}", path);
+                }
+            }
+        } else if args.write {
+            if outcome.changed {
+                if !json && args.staged {
+                    println!("Formatted content staged: {}\n", path);
+                } else if !json {
+                    println!("Formatted content written back to: {}\n", path);
+                }
             }
+        } else if args.stdin {
+            if !json {
+                // Editors replace the buffer with this, so print it as-is.
+                print!("{}", formatted);
+            }
+        } else if !json {
+            println!("{}", formatted);
+        }
+    }
+
+    if failed > 0 {
+        eprintln!("Error: {} file(s) failed to format", failed);
+        return Ok(failure_code);
+    }
+
+    if unformatted > 0 {
+        if !args.list_different {
+            eprintln!("Error: Formatting check failed");
         }
+        return Ok(EXIT_NEEDS_FORMATTING);
+    }
+
+    Ok(0)
+}
+
+// Write the result back to the file, or to the git index in `--staged` mode.
+fn write_file(args: &Args, outcome: &FileOutcome) -> Result<(), FormatError> {
+    let path = &outcome.path;
+    let formatted = outcome.formatted.as_deref().unwrap_or_default();
+    let result = if args.staged {
+        let staged = outcome.original.as_deref().unwrap_or_default();
+        git::restage(path, staged, formatted)
+    } else {
+        fs::write(path, formatted).map_err(|e| format!("Failed to write formatted content: {}", e))
+    };
+    result.map_err(|message| FormatError::Io {
+        file: path.clone(),
+        message,
+    })
+}
+
+fn create_formatter(args: &Args, source_files: Vec<String>) -> Result<Formatter, String> {
+    let mut formatter = if args.no_config {
+        Formatter::new(Config::default(), source_files)
+    } else if let Some(path) = &args.config {
+        Formatter::create_from_config(Some(path), source_files)?
+    } else {
+        Formatter::new(Config::default(), source_files).with_config_discovery()
+    };
+
+    if let Some(range) = &args.range {
+        formatter = formatter.with_range(range.clone());
+    }
+    if let Some(offset) = args.cursor_offset {
+        formatter = formatter.with_cursor_offset(offset);
+    }
+    if args.recover {
+        formatter = formatter.with_recovery();
     }
+    if args.verify {
+        formatter = formatter.with_verification();
+    }
+    formatter = formatter.with_comment_fallback(args.comment_fallback);
+    if args.verify_idempotency {
+        formatter = formatter.with_idempotency_check();
+    }
+    Ok(formatter)
+}
+
+// Format what is staged rather than the working tree files.
+fn format_staged(args: &Args) -> Result<Vec<FileOutcome>, String> {
+    let formatter = create_formatter(args, Vec::new())?;
+    if formatter.range().is_some() {
+        return Err("A range cannot be used with --staged".to_string());
+    }
+    if args.cursor_offset.is_some() {
+        return Err("A cursor offset cannot be used with --staged".to_string());
+    }
+
+    let mut outcomes = Vec::new();
+    let staged = filter_ignored(git::staged(&args.paths)?, |file| {
+        formatter.is_excluded(file)
+    });
+    for path in staged {
+        let source = git::read_staged(&path)?;
+        let config = formatter.config_for(&path).map_err(|e| e.to_string())?;
+        outcomes.push(Formatter::format_source(
+            &path,
+            source,
+            config,
+            formatter.options(),
+        ));
+    }
+    Ok(outcomes)
+}
+
+fn format_stdin(args: &Args) -> Result<FileOutcome, String> {
+    if args.paths.len() > 1 {
+        return Err("`-` (stdin) cannot be combined with other paths".to_string());
+    }
+    if args.write {
+        return Err("--write cannot be used when reading from stdin".to_string());
+    }
+
+    let mut source = String::new();
+    io::stdin()
+        .read_to_string(&mut source)
+        .map_err(|e| format!("Failed to read from stdin: {}", e))?;
+
+    let path = args.stdin_filepath.as_deref().unwrap_or("<stdin>");
+    let formatter = create_formatter(args, Vec::new())?;
 
-    Ok(())
+    let config = formatter.config_for(path).map_err(|e| e.to_string())?;
+    Ok(Formatter::format_source(
+        path,
+        source,
+        config,
+        formatter.options(),
+    ))
 }
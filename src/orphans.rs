@@ -0,0 +1,46 @@
+use crate::range::{find_counterpart, is_unit};
+use std::ops::Range;
+use tree_sitter::{Node, Tree};
+
+// Insert each erased comment on its own line before the statement or body member enclosing it,
+// or return `None` if one of them is not inside any.
+pub fn reattach(
+    source_code: &str,
+    source_tree: &Tree,
+    formatted_code: &str,
+    formatted_tree: &Tree,
+    comments: &[Range<usize>],
+) -> Option<String> {
+    // (byte in formatted_code, text to insert), in source order of the comments
+    let mut insertions: Vec<(usize, String)> = Vec::new();
+    for comment in comments {
+        let unit = enclosing_unit(source_tree.root_node(), comment.clone())?;
+        let counterpart = find_counterpart(unit, formatted_tree.root_node()).ok()?;
+
+        let line_start = formatted_code[..counterpart.start_byte()]
+            .rfind('\n')
+            .map_or(0, |i| i + 1);
+        let line = &formatted_code[line_start..];
+        let indent = &line[..line.len() - line.trim_start_matches([' ', '\t']).len()];
+        insertions.push((
+            line_start,
+            format!("{}{}\n", indent, source_code[comment.clone()].trim_end()),
+        ));
+    }
+
+    // stable, so comments before the same unit keep their order
+    insertions.sort_by_key(|(byte, _)| *byte);
+    let mut result = formatted_code.to_string();
+    for (byte, text) in insertions.into_iter().rev() {
+        result.insert_str(byte, &text);
+    }
+    Some(result)
+}
+
+fn enclosing_unit(root: Node, comment: Range<usize>) -> Option<Node> {
+    let mut node = root.descendant_for_byte_range(comment.start, comment.end)?;
+    while !is_unit(&node) {
+        node = node.parent()?;
+    }
+    Some(node)
+}
@@ -0,0 +1,186 @@
+use crate::accessor::Accessor;
+use crate::data_model::{ConstructInvocation, DocBuild, InterfaceMember};
+use crate::doc::DocRef;
+use crate::doc_builder::DocBuilder;
+use crate::enum_def::{BodyMember, ClassMember, RootMember, Statement};
+use crate::error::FormatError;
+use std::ops::Range;
+use tree_sitter::Node;
+
+// Nodes whose named children are the units a range is widened to: statements and body members.
+const CONTAINERS: [&str; 7] = [
+    "parser_output",
+    "class_body",
+    "interface_body",
+    "enum_body",
+    "constructor_body",
+    "trigger_body",
+    "block",
+];
+
+// The part of a source file to format.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FormatRange {
+    // Byte offsets, end exclusive.
+    Bytes { start: usize, end: usize },
+    // 1-based line numbers, both inclusive.
+    Lines { first: usize, last: usize },
+}
+
+impl FormatRange {
+    // Resolve to a byte range within `source_code`.
+    pub fn to_bytes(&self, source_code: &str) -> Range<usize> {
+        let len = source_code.len();
+        match *self {
+            Self::Bytes { start, end } => start.min(len)..end.clamp(start.min(len), len),
+            Self::Lines { first, last } => {
+                let line_starts: Vec<usize> = std::iter::once(0)
+                    .chain(source_code.match_indices('\n').map(|(i, _)| i + 1))
+                    .collect();
+                let start = line_starts.get(first.max(1) - 1).copied().unwrap_or(len);
+                let end = line_starts.get(last.max(first)).copied().unwrap_or(len);
+                start..end
+            }
+        }
+    }
+}
+
+// The sibling statements or body members overlapping `range`, or `None` when the range only
+// covers whitespace or comments between them. Enum constants are formatted with their enum.
+pub fn enclosing_units(root: Node, range: Range<usize>) -> Option<Vec<Node>> {
+    let mut node = root.descendant_for_byte_range(range.start, range.end.max(range.start + 1))?;
+
+    loop {
+        if is_range_container(&node) {
+            let overlapping: Vec<Node> = node
+                .children_vec()
+                .into_iter()
+                .filter(|child| child.start_byte() < range.end && child.end_byte() > range.start)
+                .collect();
+            return (!overlapping.is_empty()).then_some(overlapping);
+        }
+        if is_unit(&node) && node.parent().is_some_and(|p| is_range_container(&p)) {
+            return Some(vec![node]);
+        }
+
+        node = node.parent()?;
+    }
+}
+
+fn is_range_container(node: &Node) -> bool {
+    CONTAINERS.contains(&node.kind()) && node.kind() != "enum_body"
+}
+
+// The doc of `units`, siblings from `enclosing_units()`, at the indentation they have in the
+// formatted file. It starts with a line break, so that the first line is indented as well.
+pub fn build_units<'a>(b: &'a DocBuilder<'a>, units: &[Node]) -> Result<DocRef<'a>, FormatError> {
+    let container = units[0].parent().expect("a unit is inside a container");
+    let units = units.to_vec();
+    let doc = match container.kind() {
+        "parser_output" => {
+            let members = BodyMember::collect(units, RootMember::new, RootMember::Verbatim)?;
+            b.intersperse_body_members(&members)
+        }
+        "class_body" => {
+            let members = BodyMember::collect(units, ClassMember::new, ClassMember::Verbatim)?;
+            b.intersperse_body_members(&members)
+        }
+        "interface_body" => {
+            let members =
+                BodyMember::collect(units, InterfaceMember::new, InterfaceMember::Verbatim)?;
+            b.intersperse_body_members(&members)
+        }
+        _ if units[0].kind() == "explicit_constructor_invocation" => {
+            let mut units = units;
+            let first = units.remove(0);
+            let invocation = BodyMember::new(&first, ConstructInvocation::new(first)?);
+            let statements = BodyMember::collect(units, Statement::new, Statement::Verbatim)?;
+            let separator = match statements.is_empty() {
+                true => b.nil(),
+                false if invocation.has_trailing_newline => b.empty_new_line(),
+                false => b.nl(),
+            };
+            b.concat(vec![
+                invocation.member.build(b),
+                separator,
+                b.intersperse_body_members(&statements),
+            ])
+        }
+        _ => {
+            let statements = BodyMember::collect(units, Statement::new, Statement::Verbatim)?;
+            b.intersperse_body_members(&statements)
+        }
+    };
+
+    // one level per body or block around the units
+    let mut doc = b.concat(vec![b.nl(), doc]);
+    let mut ancestor = Some(container);
+    while let Some(node) = ancestor {
+        if node.kind() != "parser_output"
+            && (CONTAINERS.contains(&node.kind()) || node.kind() == "switch_block")
+        {
+            doc = b.indent(doc);
+        }
+        ancestor = node.parent();
+    }
+    Ok(doc)
+}
+
+pub fn is_unit(node: &Node) -> bool {
+    !node.is_extra()
+        && node.is_named()
+        && node
+            .parent()
+            .is_some_and(|parent| CONTAINERS.contains(&parent.kind()))
+}
+
+// Follow the path of `node` from the root of its tree down the other tree.
+pub fn find_counterpart<'a>(node: Node, other_root: Node<'a>) -> Result<Node<'a>, FormatError> {
+    let mut path = Vec::new();
+    let mut current = node;
+    while let Some(parent) = current.parent() {
+        let mut cursor = parent.walk();
+        let index = parent
+            .named_children(&mut cursor)
+            .filter(|child| !child.is_extra())
+            .position(|child| child == current)
+            .expect("a node is a child of its parent");
+        path.push(index);
+        current = parent;
+    }
+
+    let mut counterpart = other_root;
+    for index in path.into_iter().rev() {
+        let mut cursor = counterpart.walk();
+        let child = counterpart
+            .named_children(&mut cursor)
+            .filter(|child| !child.is_extra())
+            .nth(index);
+        counterpart = child.ok_or_else(|| FormatError::Internal {
+            file: None,
+            message: "formatted code does not match the structure of the range".to_string(),
+        })?;
+    }
+
+    if counterpart.kind() != node.kind() {
+        return Err(FormatError::Internal {
+            file: None,
+            message: format!(
+                "range maps `{}` to `{}` in the formatted code",
+                node.kind(),
+                counterpart.kind()
+            ),
+        });
+    }
+    Ok(counterpart)
+}
+
+// The start of the line of `byte` when only whitespace precedes it there.
+pub fn extend_to_line_start(code: &str, byte: usize) -> usize {
+    let line_start = code[..byte].rfind('\n').map_or(0, |i| i + 1);
+    if code[line_start..byte].trim().is_empty() {
+        line_start
+    } else {
+        byte
+    }
+}
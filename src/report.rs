@@ -0,0 +1,133 @@
+use crate::edits::{text_edits, TextEdit};
+use crate::error::FormatError;
+use crate::formatter::FileOutcome;
+use serde::Serialize;
+use similar::TextDiff;
+
+// Exit codes of the CLI; when several apply, the highest one is used.
+pub const EXIT_NEEDS_FORMATTING: i32 = 1; // --check, --diff or --list-different found changes
+pub const EXIT_FORMAT_ERROR: i32 = 2; // a file could not be parsed or formatted
+pub const EXIT_IO_ERROR: i32 = 3; // a file or config could not be read or written, or bad arguments
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileStatus {
+    Formatted,   // the formatted result differs from the file
+    Unformatted, // left as written by `--comment-fallback leave`
+    Unchanged,
+    Error,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct RecordLocation {
+    pub line: usize,
+    pub column: usize,
+}
+
+// One machine-readable line of `--message-format json` output.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct FileRecord {
+    pub path: String,
+    pub status: FileStatus,
+    pub error_kind: Option<&'static str>,
+    pub message: Option<String>,
+    pub location: Option<RecordLocation>,
+    pub warnings: Vec<RecordWarning>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub erased_comments: Vec<RecordComment>, // of an `erased_comment` error
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub edits: Vec<TextEdit>, // turning the file into its formatted version
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cursor_offset: Option<usize>, // of `--cursor-offset`, in the formatted code
+    pub duration_ms: f64,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct RecordWarning {
+    pub message: String,
+    pub location: RecordLocation,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct RecordComment {
+    pub line: usize,
+    pub column: usize,
+    pub text: String,
+}
+
+impl FileRecord {
+    pub fn new(outcome: &FileOutcome) -> Self {
+        let status = if outcome.is_error() {
+            FileStatus::Error
+        } else if outcome.changed {
+            FileStatus::Formatted
+        } else if outcome.left_unformatted {
+            FileStatus::Unformatted
+        } else {
+            FileStatus::Unchanged
+        };
+
+        let error = outcome.error.as_ref();
+        Self {
+            path: outcome.path.clone(),
+            status,
+            error_kind: error.map(|e| e.kind()),
+            message: error.map(|e| e.to_string()),
+            location: error.and_then(|e| e.location()).map(|l| RecordLocation {
+                line: l.line,
+                column: l.column,
+            }),
+            warnings: outcome
+                .warnings
+                .iter()
+                .map(|w| RecordWarning {
+                    message: w.message.clone(),
+                    location: RecordLocation {
+                        line: w.location.line,
+                        column: w.location.column,
+                    },
+                })
+                .collect(),
+            erased_comments: match error {
+                Some(FormatError::ErasedComments(locations)) => locations
+                    .iter()
+                    .map(|l| RecordComment {
+                        line: l.line,
+                        column: l.column,
+                        text: l.snippet.clone(),
+                    })
+                    .collect(),
+                _ => Vec::new(),
+            },
+            edits: match (&outcome.original, &outcome.formatted) {
+                (Some(original), Some(formatted)) if outcome.changed => {
+                    text_edits(original, formatted)
+                }
+                _ => Vec::new(),
+            },
+            cursor_offset: outcome.cursor_offset,
+            duration_ms: outcome.duration.as_secs_f64() * 1000.0,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("FileRecord serialization cannot fail")
+    }
+}
+
+// A unified diff from `original` to `formatted`, empty when they are equal.
+pub fn unified_diff(path: &str, original: &str, formatted: &str, context: usize) -> String {
+    TextDiff::from_lines(original, formatted)
+        .unified_diff()
+        .context_radius(context)
+        .header(&format!("a/{}", path), &format!("b/{}", path))
+        .to_string()
+}
+
+// The hunks of a unified diff from `original` to `formatted`, without file headers.
+pub fn diff_hunks(original: &str, formatted: &str, context: usize) -> String {
+    TextDiff::from_lines(original, formatted)
+        .unified_diff()
+        .context_radius(context)
+        .to_string()
+}
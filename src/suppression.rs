@@ -0,0 +1,78 @@
+use crate::context::Comment;
+use std::ops::Range;
+use tree_sitter::Node;
+
+const IGNORE_DIRECTIVES: [&str; 2] = ["afmt-ignore", "prettier-ignore"];
+const OFF_DIRECTIVE: &str = "afmt:off";
+const ON_DIRECTIVE: &str = "afmt:on";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Directive {
+    Ignore, // keep the next member as written
+    Off,    // keep everything up to the next `afmt:on` as written
+    On,
+}
+
+// Whether `comment` keeps the member it precedes as written.
+pub fn is_ignore(comment: &Comment, source_code: &str) -> bool {
+    let range = comment.range.start_byte..comment.range.end_byte;
+    directive(source_code, range) == Some(Directive::Ignore)
+}
+
+// The byte ranges from each `afmt:off` comment to the next `afmt:on` one, or to the end of the
+// code, in source order.
+pub fn off_regions(root: Node, source_code: &str) -> Vec<Range<usize>> {
+    let mut regions = Vec::new();
+    let mut start = None;
+    for comment in collect_comments(root) {
+        match (directive(source_code, comment.byte_range()), start) {
+            (Some(Directive::Off), None) => start = Some(comment.start_byte()),
+            (Some(Directive::On), Some(off)) => {
+                regions.push(off..comment.start_byte());
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(off) = start {
+        regions.push(off..source_code.len());
+    }
+    regions
+}
+
+// Directives are only honoured in comments starting their own line, so a trailing
+// `Integer a = 1; // afmt-ignore` leaves the next statement alone.
+fn directive(source_code: &str, range: Range<usize>) -> Option<Directive> {
+    let line_start = source_code[..range.start].rfind('\n').map_or(0, |i| i + 1);
+    if !source_code[line_start..range.start].trim().is_empty() {
+        return None;
+    }
+
+    let text = &source_code[range];
+    let text = match text.strip_prefix("//") {
+        Some(line) => line,
+        None => text.strip_prefix("/*")?.strip_suffix("*/")?,
+    };
+
+    match text.trim() {
+        OFF_DIRECTIVE => Some(Directive::Off),
+        ON_DIRECTIVE => Some(Directive::On),
+        t if IGNORE_DIRECTIVES.contains(&t) => Some(Directive::Ignore),
+        _ => None,
+    }
+}
+
+fn collect_comments(root: Node) -> Vec<Node> {
+    let mut comments = Vec::new();
+    let mut cursor = root.walk();
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        if node.is_extra() && node.kind().ends_with("comment") {
+            comments.push(node);
+            continue;
+        }
+        let children: Vec<Node> = node.children(&mut cursor).collect();
+        stack.extend(children.into_iter().rev());
+    }
+    comments
+}
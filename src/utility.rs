@@ -1,13 +1,14 @@
 use crate::{
     accessor::Accessor,
-    context::{Comment, CommentBucket, CommentMap, NodeContext},
+    context::{Comment, CommentBucket, CommentBucketRef, CommentMap, FormatContext, NodeContext},
     data_model::*,
     doc::{Doc, DocRef},
     doc_builder::DocBuilder,
     enum_def::{Comparison, SetValue, SoqlLiteral, ValueComparedWith},
-    message_helper::{red, yellow},
+    error::{ErrorLocation, FormatError},
+    message_helper::yellow,
 };
-use std::{cell::Cell, collections::HashMap};
+use std::collections::HashMap;
 use tree_sitter::{Node, Tree, TreeCursor};
 
 const SNIPPET_MAX_LEN: usize = 80;
@@ -16,59 +17,22 @@ pub fn truncate_snippet(snippet: &str) -> String {
     if snippet.len() <= SNIPPET_MAX_LEN {
         snippet.to_string()
     } else {
-        format!("{}…", &snippet[..SNIPPET_MAX_LEN])
-    }
-}
-
-thread_local! {
-    static THREAD_SOURCE_CODE: Cell<Option<&'static str>>
-        = const{ Cell::new(None) };
-}
-
-pub fn set_thread_source_code(source_code: String) {
-    // TODO: use OnceCell to not leak?
-    let leaked_code: &'static str = Box::leak(source_code.into_boxed_str());
-    THREAD_SOURCE_CODE.with(|sc| {
-        if sc.get().is_some() {
-            panic!("Source code is already set for this thread");
-        }
-        sc.set(Some(leaked_code));
-    });
-}
-
-pub fn get_source_code() -> &'static str {
-    THREAD_SOURCE_CODE.with(|sc| sc.get().expect("Source code not set for this thread"))
-}
-
-thread_local! {
-    static THREAD_COMMENT_MAP: Cell<Option<&'static CommentMap>> = const{ Cell::new(None) };
-}
-
-pub fn set_thread_comment_map(comment_map: CommentMap) {
-    // TODO: use OnceCell to not leak?
-    let leaked_map: &'static CommentMap = Box::leak(Box::new(comment_map));
-
-    THREAD_COMMENT_MAP.with(|cm| {
-        if cm.get().is_some() {
-            panic!("CommentMap is already set for this thread");
+        let mut end = SNIPPET_MAX_LEN;
+        while !snippet.is_char_boundary(end) {
+            end -= 1;
         }
-        cm.set(Some(leaked_map));
-    });
-}
-
-pub fn get_comment_bucket(node_id: &usize) -> &CommentBucket {
-    get_comment_map()
-        .get(node_id)
-        .unwrap_or_else(|| panic!("## comment_map missing bucket for node: {}", node_id))
+        format!("{}…", &snippet[..end])
+    }
 }
 
-pub fn get_comment_map() -> &'static CommentMap {
-    THREAD_COMMENT_MAP.with(|cm| cm.get().expect("## CommentMap not set for this thread"))
+pub fn get_comment_bucket(node_id: &usize) -> CommentBucketRef {
+    CommentBucketRef::new(FormatContext::current(), *node_id)
 }
 
 #[allow(dead_code)]
 pub fn print_comment_map(tree: &Tree) {
-    let comment_map = get_comment_map();
+    let context = FormatContext::current();
+    let comment_map = context.comment_map();
     let node_map = build_id_node_map(tree);
 
     let filtered_map: HashMap<usize, &CommentBucket> = comment_map
@@ -99,7 +63,7 @@ pub fn print_comment_map(tree: &Tree) {
     }
 }
 
-fn build_id_node_map(ast_tree: &Tree) -> HashMap<usize, Node> {
+fn build_id_node_map(ast_tree: &Tree) -> HashMap<usize, Node<'_>> {
     let mut cursor = ast_tree.walk();
     let mut node_map = HashMap::new();
 
@@ -119,8 +83,20 @@ fn build_id_node_map(ast_tree: &Tree) -> HashMap<usize, Node> {
     }
 }
 
-pub fn assert_no_missing_comments() {
-    let missing_comments: Vec<&'static Comment> = get_comment_map()
+// Byte ranges of the comments that were not printed, in source order.
+pub fn erased_comments() -> Vec<std::ops::Range<usize>> {
+    comment_ranges(false)
+}
+
+// The byte ranges of the comments printed so far.
+pub fn printed_comments() -> Vec<std::ops::Range<usize>> {
+    comment_ranges(true)
+}
+
+fn comment_ranges(printed: bool) -> Vec<std::ops::Range<usize>> {
+    let context = FormatContext::current();
+    let mut erased: Vec<std::ops::Range<usize>> = context
+        .comment_map()
         .values()
         .flat_map(|bucket| {
             bucket
@@ -129,29 +105,31 @@ pub fn assert_no_missing_comments() {
                 .chain(bucket.post_comments.iter())
                 .chain(bucket.dangling_comments.iter())
         })
-        .filter(|comment| !comment.is_printed())
+        .filter(|comment| comment.is_printed() == printed)
+        .map(|comment| comment.range.start_byte..comment.range.end_byte)
         .collect();
-
-    if !missing_comments.is_empty() {
-        for comment in missing_comments {
-            eprintln!("Erased comment: {}", red(&comment.value));
-        }
-        panic!("## There are erased comment node(s)");
-    }
+    erased.sort_by_key(|range| range.start);
+    erased.dedup();
+    erased
 }
 
 pub fn is_punctuation_node(node: &Node) -> bool {
     matches!(node.kind(), "," | ";")
 }
 
+// Comments are the only extras of a valid tree; ERROR nodes can be extras too.
+pub fn is_comment_node(node: &Node) -> bool {
+    node.is_extra() && !node.is_error()
+}
+
 fn is_associable_unnamed_node(node: &Node) -> bool {
     is_punctuation_node(node) || matches!(node.kind(), "else")
 }
 
-pub fn collect_comments(cursor: &mut TreeCursor, comment_map: &mut CommentMap) {
+pub fn collect_comments(cursor: &mut TreeCursor, source_code: &str, comment_map: &mut CommentMap) {
     let node = cursor.node();
 
-    if (!node.is_named() || node.is_extra()) && !is_associable_unnamed_node(&node) {
+    if (!node.is_named() || is_comment_node(&node)) && !is_associable_unnamed_node(&node) {
         return;
     }
 
@@ -173,9 +151,9 @@ pub fn collect_comments(cursor: &mut TreeCursor, comment_map: &mut CommentMap) {
     loop {
         let child = cursor.node();
 
-        if child.is_extra() {
+        if is_comment_node(&child) {
             // It's a comment node
-            let comment = Comment::from_node(child);
+            let comment = Comment::from_node(child, source_code);
 
             if let Some((last_id, last_row)) = last_associable_node_info {
                 // We'll wrap the comment in an Option so we can move it exactly once
@@ -199,7 +177,7 @@ pub fn collect_comments(cursor: &mut TreeCursor, comment_map: &mut CommentMap) {
                     let sibling = peek_cursor.node();
 
                     // If the sibling is another comment, skip it and put our comment back
-                    if sibling.is_extra() {
+                    if is_comment_node(&sibling) {
                         comment_opt = Some(c);
                         continue;
                     }
@@ -245,7 +223,7 @@ pub fn collect_comments(cursor: &mut TreeCursor, comment_map: &mut CommentMap) {
             }
 
             // Recurse down into the child node
-            collect_comments(cursor, comment_map);
+            collect_comments(cursor, source_code, comment_map);
 
             // After returning, we know child is fully processed
             last_associable_node_info = Some((child_id, child.end_position().row));
@@ -286,16 +264,16 @@ pub fn build_with_comments<'a, F>(
     F: FnOnce(&'a DocBuilder<'a>, &mut Vec<DocRef<'a>>),
 {
     let bucket = get_comment_bucket(&node_context.id);
-    handle_pre_comments(b, bucket, result);
+    handle_pre_comments(b, &bucket, result);
 
     if bucket.dangling_comments.is_empty() {
         handle_members(b, result);
     } else {
-        result.push(b.concat(handle_dangling_comments(b, bucket)));
+        result.push(b.concat(handle_dangling_comments(b, &bucket)));
         return;
     }
 
-    handle_post_comments(b, bucket, result);
+    handle_post_comments(b, &bucket, result);
 }
 
 pub fn build_with_comments_core<'a, F>(
@@ -307,12 +285,12 @@ pub fn build_with_comments_core<'a, F>(
     F: FnOnce(&'a DocBuilder<'a>, &mut Vec<DocRef<'a>>),
 {
     let bucket = get_comment_bucket(&node_context.id);
-    handle_pre_comments(b, bucket, result);
+    handle_pre_comments(b, &bucket, result);
 
     if bucket.dangling_comments.is_empty() {
         handle_members(b, result);
     } else {
-        result.push(b.concat(handle_dangling_comments(b, bucket)));
+        result.push(b.concat(handle_dangling_comments(b, &bucket)));
     }
 }
 
@@ -328,7 +306,7 @@ pub fn build_with_comments_and_punc<'a, F>(
 
     let bucket = get_comment_bucket(&node_context.id);
     if bucket.dangling_comments.is_empty() {
-        handle_post_comments(b, bucket, result);
+        handle_post_comments(b, &bucket, result);
     }
 
     if let Some(ref n) = node_context.punc {
@@ -354,7 +332,7 @@ pub fn build_with_comments_and_punc_attached<'a, F>(
     }
 
     if bucket.dangling_comments.is_empty() {
-        handle_post_comments(b, bucket, result);
+        handle_post_comments(b, &bucket, result);
     }
 }
 
@@ -404,12 +382,23 @@ pub fn handle_pre_comments<'a>(
     bucket: &CommentBucket,
     result: &mut Vec<DocRef<'a>>,
 ) {
-    if bucket.pre_comments.is_empty() {
+    handle_leading_comments(b, &bucket.pre_comments, result);
+}
+
+// `comments` are a prefix of a node's pre comments.
+pub fn handle_leading_comments<'a>(
+    b: &'a DocBuilder<'a>,
+    comments: &[Comment],
+    result: &mut Vec<DocRef<'a>>,
+) {
+    // e.g. those kept in the text of an `afmt:off` region
+    let comments: Vec<&Comment> = comments.iter().filter(|c| !c.is_printed()).collect();
+    if comments.is_empty() {
         return;
     }
 
     let mut docs = Vec::new();
-    for (i, comment) in bucket.pre_comments.iter().enumerate() {
+    for (i, comment) in comments.iter().enumerate() {
         if comment.has_leading_content() {
             docs.push(b.txt(" "));
         } else {
@@ -430,7 +419,7 @@ pub fn handle_pre_comments<'a>(
 
         if comment.has_trailing_content() {
             docs.push(b.txt(" "));
-        } else if i == bucket.pre_comments.len() - 1 {
+        } else if i == comments.len() - 1 {
             if comment.has_newline_below() {
                 docs.push(b.empty_new_line());
             } else {
@@ -448,12 +437,9 @@ pub fn handle_post_comments<'a>(
     bucket: &CommentBucket,
     result: &mut Vec<DocRef<'a>>,
 ) {
-    if bucket.post_comments.is_empty() {
-        return;
-    }
-
+    let comments = bucket.post_comments.iter().filter(|c| !c.is_printed());
     let mut docs = Vec::new();
-    for comment in &bucket.post_comments {
+    for comment in comments {
         if comment.has_leading_content() {
             docs.push(b.txt(" "));
         } else if comment.has_newline_above() {
@@ -470,10 +456,12 @@ pub fn handle_post_comments<'a>(
 
         comment.mark_as_printed();
     }
-    result.push(b.concat(docs));
+    if !docs.is_empty() {
+        result.push(b.concat(docs));
+    }
 }
 
-pub fn enrich(ast_tree: &Tree) -> Root {
+pub fn enrich(ast_tree: &Tree) -> Result<Root, FormatError> {
     let root_node = ast_tree.root_node();
     Root::new(root_node)
     // TODO: check enum size
@@ -481,18 +469,15 @@ pub fn enrich(ast_tree: &Tree) -> Root {
     //eprintln!("Class={:#?}", std::mem::size_of::<FieldDeclaration>());
 }
 
-pub fn assert_check(node: Node, expected_kind: &str) {
-    assert!(
-        node.kind() == expected_kind,
-        "## Expected node kind '{}', found '{}'.\n## Source_code: {}",
-        yellow(expected_kind),
-        red(node.kind()),
-        node.value()
-    );
+pub fn assert_check(node: Node, expected_kind: &str) -> Result<(), FormatError> {
+    if node.kind() != expected_kind {
+        return Err(unsupported_node(node, expected_kind));
+    }
+    Ok(())
 }
 
-pub fn get_precedence(op: &str) -> u8 {
-    match op {
+pub fn get_precedence(op_node: &Node) -> Result<u8, FormatError> {
+    Ok(match op_node.kind() {
         "=" | "+=" | "-=" | "*=" | "/=" | "%=" | "&=" | "|=" | "^=" | "<<=" | ">>=" | ">>>=" => 1, // Assignment
         "?" | ":" => 2,                               // Ternary
         "||" => 3,                                    // Logical OR
@@ -507,8 +492,8 @@ pub fn get_precedence(op: &str) -> u8 {
         "+" | "-" => 12,                              // Additive
         "*" | "/" | "%" => 13,                        // Multiplicative
         "!" | "~" | "++" | "--" => 14,                // Unary operators
-        _ => panic!("## Not supported operator: {}", op),
-    }
+        _ => return Err(unsupported_node(*op_node, "operator")),
+    })
 }
 
 pub fn is_binary_exp(node: &Node) -> bool {
@@ -521,28 +506,28 @@ pub fn is_query_expression(node: &Node) -> bool {
 
 // TODO: AST use a comparison concrete node so this can be moved into Comparison::new()
 // TODO: get rid of next_named()?
-pub fn get_comparsion(node: &Node) -> Comparison {
+pub fn get_comparsion(node: &Node) -> Result<Comparison, FormatError> {
     if let Some(operator_node) = node.try_c_by_k("value_comparison_operator") {
-        let next_node = operator_node.next_named();
+        let next_node = operator_node.next_named()?;
         let compared_with = match next_node.kind() {
             "bound_apex_expression" => {
-                ValueComparedWith::Bound(BoundApexExpression::new(next_node))
+                ValueComparedWith::Bound(BoundApexExpression::new(next_node)?)
             }
-            _ => ValueComparedWith::Literal(SoqlLiteral::new(next_node)),
+            _ => ValueComparedWith::Literal(SoqlLiteral::new(next_node)?),
         };
 
-        Comparison::Value(ValueComparison {
+        Ok(Comparison::Value(ValueComparison {
             operator: operator_node.value(),
             compared_with,
-        })
+        }))
     } else if let Some(operator_node) = node.try_c_by_k("set_comparison_operator") {
-        let next_node = operator_node.next_named();
-        Comparison::Set(SetComparison {
+        let next_node = operator_node.next_named()?;
+        Ok(Comparison::Set(SetComparison {
             operator: operator_node.value(),
-            set_value: SetValue::new(next_node),
-        })
+            set_value: SetValue::new(next_node)?,
+        }))
     } else {
-        unreachable!()
+        Err(unsupported_node(*node, "comparison"))
     }
 }
 
@@ -580,13 +565,28 @@ fn is_a_chaining_node(node: &Node) -> bool {
     .contains(&node.kind())
 }
 
-pub fn panic_unknown_node(node: Node, name: &str) -> ! {
-    panic!(
-        "## unknown node: {} in {}\n## Source_code: {}",
-        red(node.kind()),
-        name,
-        node.value()
-    );
+pub fn unsupported_node(node: Node, within: &str) -> FormatError {
+    FormatError::UnsupportedNode {
+        kind: node.kind().to_string(),
+        within: within.to_string(),
+        location: ErrorLocation::from_node(&node),
+    }
+}
+
+// The tree does not have the shape the data model expects, e.g. a mandatory child is missing.
+pub fn missing_child(node: &Node, what: &str) -> FormatError {
+    let location = ErrorLocation::from_node(node);
+    FormatError::Internal {
+        file: None,
+        message: format!(
+            "{}:{}: `{}` is missing a mandatory {}: `{}`",
+            location.line,
+            location.column,
+            node.kind(),
+            what,
+            location.snippet
+        ),
+    }
 }
 
 pub fn is_bracket_composite_node(node: &Node) -> bool {
@@ -0,0 +1,127 @@
+use crate::error::{ErrorLocation, FormatError};
+use tree_sitter::{Node, Tree};
+
+// A leaf of the syntax tree, normalised so that allowed formatting changes compare equal.
+#[derive(Debug)]
+pub struct Token<'a> {
+    pub text: &'a str,
+    pub start_byte: usize,
+    pub normalized: String,
+}
+
+// Check that `formatted_code` has the same tokens and comments as `source_code`.
+// Apex is case-insensitive outside of string literals, so keyword and literal casing may change;
+// comments may only change in whitespace and the ` * ` gutter of block comments. Comments can be
+// moved around punctuation, so they are compared apart from the code, in source order.
+pub fn verify(
+    source_code: &str,
+    source_tree: &Tree,
+    formatted_code: &str,
+    formatted_tree: &Tree,
+) -> Result<(), FormatError> {
+    if formatted_tree.root_node().has_error() && !source_tree.root_node().has_error() {
+        return Err(FormatError::Verification {
+            location: ErrorLocation::at(source_code, 0, ""),
+            message: "the formatted code does not parse".to_string(),
+        });
+    }
+
+    let (expected_code, expected_comments) = collect_tokens(source_tree.root_node(), source_code);
+    let (found_code, found_comments) = collect_tokens(formatted_tree.root_node(), formatted_code);
+
+    compare(source_code, &expected_code, &found_code)?;
+    compare(source_code, &expected_comments, &found_comments)
+}
+
+fn compare(source_code: &str, expected: &[Token], found: &[Token]) -> Result<(), FormatError> {
+    for i in 0..expected.len().max(found.len()) {
+        let (e, f) = (expected.get(i), found.get(i));
+        if e.map(|t| &t.normalized) == f.map(|t| &t.normalized) {
+            continue;
+        }
+
+        let start_byte = e.map_or(source_code.len(), |t| t.start_byte);
+        let describe = |token: Option<&Token>| {
+            token.map_or("end of file".to_string(), |t| format!("`{}`", t.text))
+        };
+        return Err(FormatError::Verification {
+            location: ErrorLocation::at(source_code, start_byte, e.map_or("", |t| t.text)),
+            message: format!("expected {}, found {}", describe(e), describe(f)),
+        });
+    }
+    Ok(())
+}
+
+// Code tokens and comments of the tree, in source order.
+pub fn collect_tokens<'a>(root: Node, code: &'a str) -> (Vec<Token<'a>>, Vec<Token<'a>>) {
+    let mut tokens = Vec::new();
+    let mut comments = Vec::new();
+    let mut cursor = root.walk();
+    let mut stack = vec![root];
+
+    while let Some(node) = stack.pop() {
+        let is_leaf = node.child_count() == 0 || node.kind() == "string_literal";
+        if !is_leaf {
+            let children: Vec<Node> = node.children(&mut cursor).collect();
+            stack.extend(children.into_iter().rev());
+            continue;
+        }
+        if node.byte_range().is_empty() || is_optional(&node) {
+            continue;
+        }
+
+        let text = &code[node.byte_range()];
+        let (normalized, list) = match node.kind() {
+            "string_literal" => (text.to_string(), &mut tokens),
+            "line_comment" => (
+                text.split_whitespace().collect::<Vec<_>>().join(" "),
+                &mut comments,
+            ),
+            "block_comment" => (strip_gutter(text), &mut comments),
+            // e.g. `> =` is printed as `>=`
+            _ => (
+                text.chars()
+                    .filter(|c| !c.is_whitespace())
+                    .collect::<String>()
+                    .to_lowercase(),
+                &mut tokens,
+            ),
+        };
+        list.push(Token {
+            text,
+            start_byte: node.start_byte(),
+            normalized,
+        });
+    }
+    (tokens, comments)
+}
+
+// The text of a block comment without the `*` gutter starting its lines, whitespace collapsed:
+// ` * a` and `a` are the same line, and a `/*****` banner may be split into `/**` and ` * ***`,
+// but `2 * 3` is kept.
+fn strip_gutter(text: &str) -> String {
+    let text = text.strip_prefix("/*").unwrap_or(text);
+    let text = text.strip_suffix("*/").unwrap_or(text);
+    let lines = text
+        .lines()
+        .map(|line| line.trim_start_matches(|c: char| c == '*' || c.is_whitespace()));
+    lines
+        .flat_map(str::split_whitespace)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Tokens the formatter may drop without changing the meaning of the code:
+// the parentheses of an empty annotation argument list (`@IsTest()`), grouping parentheses
+// of SOQL conditions and the `AS` of a SOQL alias.
+fn is_optional(node: &Node) -> bool {
+    let Some(parent) = node.parent() else {
+        return false;
+    };
+    match (node.kind(), parent.kind()) {
+        ("(" | ")", "annotation_argument_list") => parent.named_child_count() == 0,
+        ("(" | ")", "where_clause" | "and_expression" | "or_expression") => true,
+        (_, "storage_alias") => node.kind().eq_ignore_ascii_case("as"),
+        _ => false,
+    }
+}
@@ -0,0 +1,37 @@
+use crate::formatter::{Config, Formatter};
+use wasm_bindgen::prelude::*;
+
+// What `format()` returns to JS: `{ ok: true, output }` or `{ ok: false, error }`.
+#[wasm_bindgen(getter_with_clone)]
+pub struct FormatResult {
+    pub ok: bool,
+    pub output: Option<String>,
+    pub error: Option<String>,
+}
+
+// Format `source` with the settings of `.afmt.toml` given as JSON, e.g. `{"max_width": 100}`;
+// an empty string uses the defaults. Formatting runs on the calling thread, without the worker
+// pool, file system or cache of the CLI. Every `FormatError` comes back as `{ ok: false }`.
+#[wasm_bindgen]
+pub fn format(source: &str, config_json: &str) -> FormatResult {
+    let config = if config_json.trim().is_empty() {
+        Ok(Config::default())
+    } else {
+        Config::from_json(config_json)
+    };
+    let result =
+        config.and_then(|config| Formatter::format_one(source, config).map_err(|e| e.to_string()));
+
+    match result {
+        Ok(output) => FormatResult {
+            ok: true,
+            output: Some(output),
+            error: None,
+        },
+        Err(error) => FormatResult {
+            ok: false,
+            output: None,
+            error: Some(error),
+        },
+    }
+}
@@ -104,7 +104,7 @@ format_files() {
 
     # echo "Processing file: $FILE_PATH"
 
-    OUTPUT=$($FORMATTER_BINARY "$FILE_PATH" 2>&1)
+    OUTPUT=$($FORMATTER_BINARY --no-cache "$FILE_PATH" 2>&1)
     EXIT_CODE=$?
 
     if [ $EXIT_CODE -ne 0 ]; then
@@ -136,27 +136,16 @@ format_files() {
 
 idempotent_test() {
     local FILE_PATH="$1"
-    TMP1=$(mktemp)
-    TMP2=$(mktemp)
 
-    # Format once and save output to TMP1
-    $FORMATTER_BINARY "$FILE_PATH" > "$TMP1" 2>/dev/null
-
-    # Format the result of the first formatting and save to TMP2
-    $FORMATTER_BINARY "$TMP1" > "$TMP2" 2>/dev/null
-
-    # Capture detailed diff output
-    DIFF_OUTPUT=$(diff "$TMP1" "$TMP2")
-    if [ -n "$DIFF_OUTPUT" ]; then
+    # Format twice; a failure carries the diff between both passes
+    if OUTPUT=$($FORMATTER_BINARY --no-cache --verify-idempotency "$FILE_PATH" 2>&1 >/dev/null); then
+        echo "Idempotency test passed for $FILE_PATH"
+    elif echo "$OUTPUT" | grep -q "not idempotent"; then
         echo "Idempotency test failed for $FILE_PATH" >> "$LOG_FILE"
         echo "Diff details:" >> "$LOG_FILE"
-        echo "$DIFF_OUTPUT" >> "$LOG_FILE"
+        echo "$OUTPUT" >> "$LOG_FILE"
         echo "Difference found in idempotency test for: $FILE_PATH"
-    else
-        echo "Idempotency test passed for $FILE_PATH"
     fi
-
-    rm -f "$TMP1" "$TMP2"
 }
 
 export -f format_files
@@ -1,6 +1,12 @@
 #[cfg(test)]
 mod tests {
+    use sf_afmt::cache::Cache;
+    use sf_afmt::edits::text_edits;
+    use sf_afmt::error::FormatError;
+    use sf_afmt::files::{collect_source_files, filter_ignored};
     use sf_afmt::message_helper::red;
+    use sf_afmt::range::FormatRange;
+    use sf_afmt::report::{unified_diff, FileRecord, FileStatus};
     use sf_afmt::{formatter::*, message_helper::yellow};
     use similar::{ChangeTag, TextDiff};
     use std::fs::File;
@@ -63,6 +69,986 @@ mod tests {
         }
     }
 
+    #[test]
+    fn collect_source_files_from_dirs_and_globs() {
+        let files = collect_source_files(&["tests/static".to_string()], false, |_| false).unwrap();
+        assert!(!files.is_empty());
+        assert!(files.iter().all(|f| f.ends_with(".cls")));
+
+        let globbed =
+            collect_source_files(&["tests/st*ic/*".to_string()], false, |_| false).unwrap();
+        assert_eq!(files, globbed);
+
+        let missing = collect_source_files(&["tests/missing.cls".to_string()], false, |_| false);
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    fn ignored_and_excluded_files_are_skipped() {
+        let formatter = Formatter::new(Config::default(), Vec::new()).with_config_discovery();
+        let files = collect_source_files(&["tests/ignore".to_string()], false, |file| {
+            formatter.is_excluded(file)
+        })
+        .unwrap();
+
+        assert_eq!(files, ["tests/ignore/Kept.cls"]);
+
+        // as listed by git for `--changed-since` and `--staged`
+        let listed = [
+            "tests/ignore/Excluded.cls",
+            "tests/ignore/Kept.cls",
+            "tests/ignore/generated/Generated.cls",
+            "tests/ignore/vendor/Vendor.cls",
+        ];
+        let files = filter_ignored(listed.map(String::from).to_vec(), |file| {
+            formatter.is_excluded(file)
+        });
+        assert_eq!(files, ["tests/ignore/Kept.cls"]);
+
+        // as named on the command line, e.g. by a pre-commit hook
+        let named = listed.map(String::from).to_vec();
+        let files =
+            collect_source_files(&named, false, |file| formatter.is_excluded(file)).unwrap();
+        assert_eq!(files, ["tests/ignore/Kept.cls"]);
+        let files = collect_source_files(&named, true, |file| formatter.is_excluded(file)).unwrap();
+        assert_eq!(files, listed);
+    }
+
+    #[test]
+    fn bad_ignore_file_does_not_stop_the_walk() {
+        let dir = std::env::temp_dir().join(format!("afmt_bad_ignore_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join(".afmtignore"), "{a,b\nB.cls\n").unwrap();
+        std::fs::write(dir.join("A.cls"), "class A {}\n").unwrap();
+        std::fs::write(dir.join("sub/B.cls"), "class B {}\n").unwrap();
+
+        let files =
+            collect_source_files(&[dir.to_string_lossy().to_string()], false, |_| false).unwrap();
+        assert_eq!(files, [dir.join("A.cls").to_string_lossy()]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn format_reports_outcomes_in_input_order() {
+        let files = vec![
+            "tests/static/Ternary.in".to_string(),
+            "tests/static/Ternary.cls".to_string(),
+            "tests/static/missing.cls".to_string(),
+        ];
+        let formatter =
+            Formatter::create_from_config(Some("tests/configs/.afmt_static.toml"), files.clone())
+                .unwrap();
+        let outcomes = formatter.format();
+
+        let paths: Vec<_> = outcomes.iter().map(|o| o.path.clone()).collect();
+        assert_eq!(paths, files);
+        assert!(outcomes[0].changed);
+        assert!(!outcomes[1].changed);
+        assert_eq!(outcomes[0].formatted, outcomes[1].original);
+        assert!(outcomes[2].is_error());
+    }
+
+    #[test]
+    fn panic_fails_only_its_file() {
+        let files = vec![
+            "tests/static/Ternary.in".to_string(),
+            "tests/static/Ternary.cls".to_string(),
+        ];
+        // skips the config validation, so the printer panics on every file
+        let mut config = Config::default();
+        config.indent_size = 0;
+        let outcomes = Formatter::new(config, files).format();
+
+        assert_eq!(outcomes.len(), 2);
+        for outcome in outcomes {
+            match outcome.error {
+                Some(FormatError::Internal { file, message }) => {
+                    assert_eq!(file.as_deref(), Some(outcome.path.as_str()));
+                    assert_eq!(message, "afmt panicked");
+                }
+                other => panic!("expected an internal error, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn single_worker_formats_many_files() {
+        let mut inputs: Vec<String> = std::fs::read_dir("tests/prettier80")
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "in"))
+            .map(|path| path.to_string_lossy().to_string())
+            .collect();
+        inputs.sort();
+
+        let formatter =
+            Formatter::create_from_config(Some("tests/configs/.afmt_p80.toml"), inputs.clone())
+                .unwrap()
+                .with_jobs(1);
+
+        for outcome in formatter.format() {
+            let expected = Path::new(&outcome.path).with_extension("cls");
+            let expected = std::fs::read_to_string(expected).unwrap();
+            assert_eq!(outcome.formatted.as_deref(), Some(expected.as_str()));
+        }
+    }
+
+    #[test]
+    fn format_one_runs_repeatedly_on_one_thread() {
+        let source = std::fs::read_to_string("tests/static/Ternary.in").unwrap();
+        let expected = std::fs::read_to_string("tests/static/Ternary.cls").unwrap();
+        let config = Config::from_file("tests/configs/.afmt_static.toml").unwrap();
+
+        for _ in 0..3 {
+            assert_eq!(
+                Formatter::format_one(&source, config.clone()).unwrap(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn syntax_error_is_returned_not_panicked() {
+        let source = "public class A {\n  void m() {\n    Integer x = ;\n  }\n}\n";
+        let error = Formatter::format_one(source, Config::default()).unwrap_err();
+
+        assert_eq!(error.kind(), "syntax");
+        assert_eq!(error.location().map(|l| l.line), Some(3));
+    }
+
+    #[test]
+    fn unsupported_node_is_returned_not_panicked() {
+        let source = "class A {\n  List<Event> events = [SELECT TYPEOF What WHEN Account THEN Phone END FROM Event];\n}\n";
+        let error = Formatter::format_one(source, Config::default()).unwrap_err();
+
+        assert_eq!(error.kind(), "unsupported_node");
+        assert_eq!(error.location().map(|l| l.line), Some(2));
+    }
+
+    #[test]
+    fn syntax_error_lists_every_error_node() {
+        let source = "class A {\n  void m() {\n    Integer x = ;\n    Integer y = 1\n  }\n}\n";
+        let error = Formatter::format_one(source, Config::default()).unwrap_err();
+
+        let rendered = error.with_file("A.cls").to_string();
+        let expected = "\
+error: unexpected `=`
+ --> A.cls:3:15
+  |
+3 |     Integer x = ;
+  |               ^
+
+error: missing `;`
+ --> A.cls:4:18
+  |
+4 |     Integer y = 1
+  |                  ^";
+        assert_eq!(rendered, expected);
+    }
+
+    #[test]
+    fn json_record_per_outcome() {
+        let files = vec![
+            "tests/static/Ternary.in".to_string(),
+            "tests/static/Ternary.cls".to_string(),
+            "tests/static/missing.cls".to_string(),
+        ];
+        let formatter =
+            Formatter::create_from_config(Some("tests/configs/.afmt_static.toml"), files).unwrap();
+        let records: Vec<serde_json::Value> = formatter
+            .format()
+            .iter()
+            .map(|o| serde_json::from_str(&FileRecord::new(o).to_json()).unwrap())
+            .collect();
+
+        let statuses: Vec<_> = records.iter().map(|r| r["status"].clone()).collect();
+        assert_eq!(statuses, ["formatted", "unchanged", "error"]);
+        assert_eq!(records[1]["error_kind"], serde_json::Value::Null);
+        assert_eq!(records[2]["error_kind"], "io");
+        assert!(records[0]["duration_ms"].is_number());
+    }
+
+    #[test]
+    fn unified_diff_honours_context_lines() {
+        let original = "a\nb\nc\nd\ne\n";
+        let formatted = "a\nb\nC\nd\ne\n";
+
+        let diff = unified_diff("A.cls", original, formatted, 1);
+        assert_eq!(
+            diff,
+            "--- a/A.cls\n+++ b/A.cls\n@@ -2,3 +2,3 @@\n b\n-c\n+C\n d\n"
+        );
+        assert_eq!(unified_diff("A.cls", original, original, 3), "");
+    }
+
+    #[test]
+    fn range_formats_only_enclosing_statements() {
+        let source = "\
+public class R {
+      Integer   a   =  1;
+  void m() {
+         Integer x   =  1;
+      Integer y=2;
+  }
+}
+";
+        let range = FormatRange::Lines { first: 5, last: 5 };
+        let formatted = Formatter::format_range(source, Config::default(), &range).unwrap();
+
+        let expected = "\
+public class R {
+      Integer   a   =  1;
+  void m() {
+         Integer x   =  1;
+    Integer y = 2;
+  }
+}
+";
+        assert_eq!(formatted, expected);
+    }
+
+    #[test]
+    fn range_ignores_the_rest_of_the_file() {
+        let source = "\
+public class R {
+      Integer   a   =  1;
+
+  void m() {
+      Integer y=2;
+
+    Integer z = ;
+  }
+}
+";
+        let format = |first, last| {
+            let range = FormatRange::Lines { first, last };
+            Formatter::format_range(source, Config::default(), &range)
+        };
+
+        // a syntax error outside of the range
+        let formatted = format(5, 5).unwrap();
+        assert_eq!(
+            formatted,
+            source.replace("      Integer y=2;", "    Integer y = 2;")
+        );
+
+        // blank lines between members and between statements
+        assert_eq!(format(3, 3).unwrap(), source);
+        assert_eq!(format(6, 6).unwrap(), source);
+
+        assert!(matches!(format(7, 7), Err(FormatError::Syntax(_))));
+    }
+
+    #[test]
+    fn cursor_offset_follows_its_token() {
+        let source = "\
+public class C {
+        public void   foo( Integer x ){
+    // note
+  Integer   y=x+1 ;
+}
+}
+";
+        let formatted = Formatter::format_one(source, Config::default()).unwrap();
+        let cursor = |offset: usize| {
+            let (code, cursor) =
+                Formatter::format_with_cursor(source, Config::default(), offset).unwrap();
+            assert_eq!(code, formatted);
+            cursor
+        };
+
+        // inside a token, inside a comment, and in the whitespace after a token
+        let x = source.find("x )").unwrap();
+        assert_eq!(cursor(x), formatted.find("x)"));
+        let note = source.find("ote").unwrap();
+        assert_eq!(cursor(note), formatted.find("ote"));
+        let after_one = source.find("1 ;").unwrap() + 2;
+        assert_eq!(cursor(after_one), Some(formatted.find("1;").unwrap() + 1));
+        assert_eq!(cursor(usize::MAX), Some(formatted.len()));
+    }
+
+    #[test]
+    fn recovery_keeps_broken_members_as_written() {
+        let source = "\
+public class E {
+    Integer   a   =  1;
+  void m() {
+         Integer x   =  ;
+      Integer y=2;
+  }
+}
+";
+        let expected = "\
+public class E {
+  Integer a = 1;
+  void m() {
+    Integer x   =  ;
+    Integer y = 2;
+  }
+}
+";
+        let options = FormatOptions {
+            recover: true,
+            ..FormatOptions::default()
+        };
+        let outcome =
+            Formatter::format_source("E.cls", source.to_string(), Config::default(), &options);
+
+        assert_eq!(outcome.formatted.as_deref(), Some(expected));
+        assert_eq!(outcome.warnings.len(), 1);
+        assert_eq!(outcome.warnings[0].location.to_string(), "E.cls:4:10");
+        assert!(Formatter::format_one(source, Config::default()).is_err());
+    }
+
+    #[test]
+    fn recovery_keeps_only_the_broken_enum_constant() {
+        let source = "\
+public enum Color {
+    RED,
+  GREEN = 1,
+      BLUE
+}
+";
+        let expected = "\
+public enum Color {
+  RED,
+  GREEN = 1,
+  BLUE
+}
+";
+        let options = FormatOptions {
+            recover: true,
+            ..FormatOptions::default()
+        };
+        let outcome =
+            Formatter::format_source("C.cls", source.to_string(), Config::default(), &options);
+
+        assert_eq!(outcome.formatted.as_deref(), Some(expected));
+        assert_eq!(outcome.warnings.len(), 1);
+        assert_eq!(outcome.warnings[0].location.to_string(), "C.cls:3:3");
+    }
+
+    #[test]
+    fn verification_accepts_allowed_normalisations() {
+        let source = "\
+@IsTest()
+PUBLIC class V {
+  void m() {
+    /* a
+     * b */
+    Integer i = 6; /* 2 * 3 */
+    List<Contact> cs = [SELECT Id FROM Contact AS c WHERE (Name = 'x' AND Id != null)];
+    Boolean b = 1 > = 2; // t
+  }
+}
+";
+        let options = FormatOptions {
+            verify: true,
+            ..FormatOptions::default()
+        };
+        let outcome =
+            Formatter::format_source("V.cls", source.to_string(), Config::default(), &options);
+
+        assert!(outcome.error.is_none(), "{:?}", outcome.error);
+        assert!(outcome.changed);
+    }
+
+    #[test]
+    fn idempotency_check_reports_second_pass_diff() {
+        // a known unstable case, see tests/to-do/Many_Comments2.in
+        let unstable = "\
+class A {
+  void m() {
+    for (
+      Contact a : [SELECT Id FROM Contact]
+      // c
+    ) {
+    }
+  }
+}
+";
+        match Formatter::format_idempotent(unstable, Config::default()) {
+            Err(e @ FormatError::Unstable { .. }) => {
+                assert_eq!(e.kind(), "unstable");
+                assert!(e
+                    .to_string()
+                    .contains("+    for (Contact a : [SELECT Id FROM Contact] // c"));
+            }
+            other => panic!("expected an unstable error, got {:?}", other),
+        }
+
+        let stable = "class A {\n  Integer a = 1;\n}\n";
+        assert_eq!(
+            Formatter::format_idempotent(stable, Config::default()).as_deref(),
+            Ok(stable)
+        );
+    }
+
+    #[test]
+    fn erased_comments_are_reported_or_recovered() {
+        // the comment inside the SOSL query is not printed by the formatter
+        let source = "\
+class A {
+  void m() {
+    a = [FIND 'x' /*c*/ IN ALL FIELDS];
+    // keep
+    b = 1;
+  }
+}
+";
+        let format = |comment_fallback| {
+            let options = FormatOptions {
+                comment_fallback,
+                ..FormatOptions::default()
+            };
+            Formatter::format_source("A.cls", source.to_string(), Config::default(), &options)
+        };
+
+        let failed = format(CommentFallback::Fail);
+        let record = FileRecord::new(&failed);
+        assert_eq!(record.error_kind, Some("erased_comment"));
+        assert_eq!(record.erased_comments.len(), 1);
+        assert_eq!(
+            (
+                record.erased_comments[0].line,
+                record.erased_comments[0].text.as_str()
+            ),
+            (3, "/*c*/")
+        );
+
+        let reattached = format(CommentFallback::Reattach);
+        assert_eq!(
+            reattached.formatted.as_deref(),
+            Some(
+                "class A {\n  void m() {\n    /*c*/\n    a = [FIND 'x' IN ALL FIELDS];\n    // keep\n    b = 1;\n  }\n}\n"
+            )
+        );
+        assert_eq!(reattached.warnings.len(), 1);
+
+        let left = format(CommentFallback::Leave);
+        assert_eq!(left.formatted.as_deref(), Some(source));
+        assert!(!left.changed);
+        assert!(left.needs_formatting());
+        assert_eq!(FileRecord::new(&left).status, FileStatus::Unformatted);
+        assert_eq!(left.warnings[0].location.to_string(), "A.cls:3:19");
+    }
+
+    #[test]
+    fn ignore_comments_and_off_regions_keep_source() {
+        let source = "\
+public class S {
+    // afmt-ignore
+    Integer[]   table = new Integer[]{ 1,  2,
+                                       3,  4 };
+      Integer   a   =  1;
+  void m() {
+    // afmt:off
+         Integer x   =  1;
+         Integer   y =   2;   // trailing
+           // inside
+    // afmt:on
+      Integer z=2;
+  }
+}
+";
+        let expected = "\
+public class S {
+  // afmt-ignore
+  Integer[]   table = new Integer[]{ 1,  2,
+                                       3,  4 };
+  Integer a = 1;
+  void m() {
+    // afmt:off
+         Integer x   =  1;
+         Integer   y =   2;   // trailing
+           // inside
+    // afmt:on
+    Integer z = 2;
+  }
+}
+";
+        let formatted = Formatter::format_one(source, Config::default()).unwrap();
+        assert_eq!(formatted, expected);
+        assert_eq!(
+            Formatter::format_one(&formatted, Config::default()).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn trailing_ignore_comment_is_not_a_directive() {
+        let source = "\
+public class S {
+  void m() {
+    Integer a=1; // afmt-ignore
+      Integer   b   =  2;
+  }
+}
+";
+        let expected = "\
+public class S {
+  void m() {
+    Integer a = 1; // afmt-ignore
+    Integer b = 2;
+  }
+}
+";
+        let formatted = Formatter::format_one(source, Config::default()).unwrap();
+        assert_eq!(formatted, expected);
+    }
+
+    #[test]
+    fn nearest_config_file_is_discovered() {
+        let formatter = Formatter::new(Config::default(), Vec::new()).with_config_discovery();
+
+        let nested = formatter.config_for("tests/configs/nested/A.cls").unwrap();
+        assert_eq!(nested.indent_size(), 8);
+        let root = formatter.config_for("tests/static/Ternary.in").unwrap();
+        assert_eq!(root.indent_size(), 4);
+
+        // without discovery, no config file means the defaults
+        let defaults = Formatter::create_from_config(None, Vec::new()).unwrap();
+        let config = defaults.config_for("tests/configs/nested/A.cls").unwrap();
+        assert_eq!(config.indent_size(), 2);
+    }
+
+    #[test]
+    fn stdin_is_formatted_to_stdout() {
+        let source = std::fs::read_to_string("tests/static/Ternary.in").unwrap();
+        let expected = std::fs::read_to_string("tests/static/Ternary.cls").unwrap();
+
+        let mut child = Command::new(env!("CARGO_BIN_EXE_afmt"))
+            .args(["-c", "tests/configs/.afmt_static.toml", "-"])
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(source.as_bytes())
+            .unwrap();
+        let output = child.wait_with_output().unwrap();
+
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+    }
+
+    #[test]
+    fn exit_codes_tell_failures_apart() {
+        let afmt = |args: &[&str]| {
+            Command::new(env!("CARGO_BIN_EXE_afmt"))
+                .args(["--no-cache", "-c", "tests/configs/.afmt_static.toml"])
+                .args(args)
+                .output()
+                .unwrap()
+        };
+
+        assert_eq!(
+            afmt(&["--check", "tests/static/Ternary.cls"]).status.code(),
+            Some(0)
+        );
+        assert_eq!(
+            afmt(&["--check", "tests/static/Ternary.in"]).status.code(),
+            Some(1)
+        );
+        assert_eq!(afmt(&["tests/static/missing.cls"]).status.code(), Some(3));
+
+        let listed = afmt(&["-l", "tests/static/Ternary.in", "tests/static/Ternary.cls"]);
+        assert_eq!(listed.status.code(), Some(1));
+        assert_eq!(
+            String::from_utf8(listed.stdout).unwrap(),
+            "tests/static/Ternary.in\n"
+        );
+
+        let dir = std::env::temp_dir().join(format!("afmt_exit_codes_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let broken = dir.join("Syntax.cls");
+        std::fs::write(&broken, "public class {").unwrap();
+        assert_eq!(afmt(&[broken.to_str().unwrap()]).status.code(), Some(2));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn staged_content_is_formatted_and_restaged() {
+        let repo = std::env::temp_dir().join(format!("afmt_staged_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&repo);
+        std::fs::create_dir_all(&repo).unwrap();
+        let git = |args: &[&str]| {
+            let output = Command::new("git")
+                .args(args)
+                .current_dir(&repo)
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "git {:?}", args);
+            String::from_utf8(output.stdout).unwrap()
+        };
+
+        git(&["init", "-q"]);
+        std::fs::write(repo.join("A.cls"), "class A {Integer a=1;}\n").unwrap();
+        git(&["add", "A.cls"]);
+        // an unstaged change that must survive
+        std::fs::write(repo.join("A.cls"), "class A {Integer a=1;}\n// wip\n").unwrap();
+
+        let output = Command::new(env!("CARGO_BIN_EXE_afmt"))
+            .args(["--no-config", "--staged", "--write"])
+            .current_dir(&repo)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+
+        assert_eq!(git(&["show", ":A.cls"]), "class A {\n  Integer a = 1;\n}\n");
+        assert_eq!(
+            std::fs::read_to_string(repo.join("A.cls")).unwrap(),
+            "class A {Integer a=1;}\n// wip\n"
+        );
+
+        // from a subdirectory, where paths are relative to it
+        std::fs::create_dir_all(repo.join("sub")).unwrap();
+        std::fs::write(repo.join("sub/B.cls"), "class B {Integer b=1;}\n").unwrap();
+        git(&["add", "sub/B.cls"]);
+        let output = Command::new(env!("CARGO_BIN_EXE_afmt"))
+            .args(["--no-config", "--staged", "--write"])
+            .current_dir(repo.join("sub"))
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        assert_eq!(
+            git(&["show", ":sub/B.cls"]),
+            "class B {\n  Integer b = 1;\n}\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(repo.join("sub/B.cls")).unwrap(),
+            "class B {\n  Integer b = 1;\n}\n"
+        );
+        std::fs::remove_dir_all(&repo).unwrap();
+    }
+
+    #[test]
+    fn changed_since_only_checks_changed_files() {
+        let repo = std::env::temp_dir().join(format!("afmt_changed_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&repo);
+        std::fs::create_dir_all(&repo).unwrap();
+        let git = |args: &[&str]| {
+            let output = Command::new("git")
+                .args(["-c", "user.name=afmt", "-c", "user.email=afmt@example.com"])
+                .args(args)
+                .current_dir(&repo)
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "git {:?}", args);
+        };
+
+        git(&["init", "-q"]);
+        std::fs::write(repo.join("A.cls"), "class A {Integer a=1;}\n").unwrap();
+        std::fs::write(repo.join("B.cls"), "class B {\n  Integer b = 1;\n}\n").unwrap();
+        git(&["add", "."]);
+        git(&["commit", "-q", "-m", "base"]);
+        std::fs::write(repo.join("B.cls"), "class B {Integer b=2;}\n").unwrap();
+
+        let output = Command::new(env!("CARGO_BIN_EXE_afmt"))
+            .args(["--no-config", "--no-cache", "-l", "--changed-since", "HEAD"])
+            .current_dir(&repo)
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(1));
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "B.cls\n");
+        std::fs::remove_dir_all(&repo).unwrap();
+    }
+
+    #[test]
+    fn cache_remembers_formatted_files() {
+        let dir = std::env::temp_dir().join(format!("afmt_cache_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let files = vec![
+            "tests/static/Ternary.in".to_string(),
+            "tests/static/Ternary.cls".to_string(),
+        ];
+        let config = Config::from_file("tests/configs/.afmt_static.toml").unwrap();
+        let format = || {
+            Formatter::new(config.clone(), files.clone())
+                .with_cache(&dir)
+                .format()
+        };
+        let cached_keys = || {
+            let cache_file = std::fs::read_dir(&dir).unwrap().next().unwrap().unwrap();
+            std::fs::read_to_string(cache_file.path())
+                .unwrap()
+                .lines()
+                .count()
+        };
+
+        let first = format();
+        assert_eq!(cached_keys(), 1); // only the file that is already formatted
+
+        let second = format();
+        assert_eq!(cached_keys(), 1);
+        for (a, b) in first.iter().zip(&second) {
+            assert_eq!((a.changed, &a.formatted), (b.changed, &b.formatted));
+        }
+
+        let source = std::fs::read_to_string("tests/static/Ternary.cls").unwrap();
+        let options = FormatOptions::default();
+        let cache = Cache::load(&dir);
+        assert_ne!(
+            cache.key(&source, &config, &options),
+            cache.key(&source, &Config::new(120), &options)
+        );
+        // stable across runs of the same build
+        assert_eq!(
+            cache.key(&source, &config, &options),
+            Cache::load(&dir).key(&source, &config, &options)
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn c_api_formats_and_reports_errors() {
+        use sf_afmt::ffi::*;
+        use std::ffi::{CStr, CString};
+
+        let source = "public class A {\n        Integer   x=1;\n}\n";
+        let config = CString::new("indent_size = 4").unwrap();
+        let mut out = std::ptr::null_mut();
+        unsafe {
+            let code = afmt_format(
+                source.as_ptr().cast(),
+                source.len(),
+                config.as_ptr(),
+                &mut out,
+            );
+            assert_eq!(code, AFMT_OK);
+            assert!(afmt_last_error().is_null());
+            assert_eq!(
+                CStr::from_ptr(out).to_str().unwrap(),
+                "public class A {\n    Integer x = 1;\n}\n"
+            );
+            afmt_free(out);
+
+            let broken = "public class A {";
+            let code = afmt_format(
+                broken.as_ptr().cast(),
+                broken.len(),
+                std::ptr::null(),
+                &mut out,
+            );
+            assert_eq!(code, AFMT_FORMAT_ERROR);
+            assert!(out.is_null());
+            let error = CStr::from_ptr(afmt_last_error()).to_str().unwrap();
+            assert!(error.contains("missing `}`"), "{}", error);
+
+            let code = afmt_format(std::ptr::null(), 0, std::ptr::null(), &mut out);
+            assert_eq!(code, AFMT_INVALID_ARGUMENT);
+        }
+    }
+
+    #[cfg(feature = "wasm")]
+    #[test]
+    fn wasm_format_returns_output_or_error() {
+        use sf_afmt::wasm::format;
+
+        let source = "public class A {\n        Integer   x=1;\n}\n";
+        let result = format(source, "");
+        assert!(result.ok);
+        assert_eq!(
+            result.output.as_deref(),
+            Some("public class A {\n  Integer x = 1;\n}\n")
+        );
+
+        let result = format(source, r#"{"indent_size": 4}"#);
+        assert_eq!(
+            result.output.as_deref(),
+            Some("public class A {\n    Integer x = 1;\n}\n")
+        );
+
+        let result = format("public class A {", "");
+        assert!(!result.ok);
+        assert!(result.error.unwrap().contains("missing `}`"));
+
+        let unsupported = "class A {\n  List<Event> e = [SELECT TYPEOF What WHEN Account THEN Phone END FROM Event];\n}\n";
+        let result = format(unsupported, "");
+        assert!(!result.ok);
+        assert!(result
+            .error
+            .unwrap()
+            .contains("unsupported node `type_of_clause`"));
+        assert!(format(source, "").ok);
+
+        let result = format(source, r#"{"indent_size": 0}"#);
+        assert!(!result.ok);
+        assert!(result.output.is_none());
+        assert_eq!(
+            result.error.as_deref(),
+            Some("indent_size must be greater than 0")
+        );
+    }
+
+    #[cfg(feature = "lsp")]
+    #[test]
+    fn lsp_server_formats_and_reports_syntax_errors() {
+        use lsp_server::{Connection, Message, Notification, Request, RequestId};
+        use serde_json::{json, Value};
+
+        let (client, server) = Connection::memory();
+        let handle = std::thread::spawn(move || sf_afmt::lsp::run(server).unwrap());
+
+        let mut next_id = 0;
+        let mut request = |method: &str, params: Value| -> Value {
+            next_id += 1;
+            let request = Request::new(RequestId::from(next_id), method.to_string(), params);
+            client.sender.send(Message::Request(request)).unwrap();
+            loop {
+                match client.receiver.recv().unwrap() {
+                    Message::Response(response) => return response.result.unwrap_or(Value::Null),
+                    _ => continue, // diagnostics
+                }
+            }
+        };
+        let notify = |method: &str, params: Value| {
+            let notification = Notification::new(method.to_string(), params);
+            client
+                .sender
+                .send(Message::Notification(notification))
+                .unwrap();
+        };
+        let diagnostics = || loop {
+            if let Message::Notification(n) = client.receiver.recv().unwrap() {
+                assert_eq!(n.method, "textDocument/publishDiagnostics");
+                return n.params["diagnostics"].as_array().unwrap().clone();
+            }
+        };
+
+        let init = request("initialize", json!({ "capabilities": {} }));
+        assert_eq!(init["capabilities"]["documentFormattingProvider"], true);
+        notify("initialized", json!({}));
+
+        let uri = "file:///nowhere/A.cls";
+        let source = "class A {\n      Integer a=1;\nvoid m() {\n  Integer b=2;\n  }\n}\n";
+        let document = json!({ "uri": uri });
+        notify(
+            "textDocument/didOpen",
+            json!({ "textDocument": {
+                "uri": uri, "languageId": "apex", "version": 1,
+                "text": source
+            }}),
+        );
+        assert!(diagnostics().is_empty());
+
+        let options = json!({ "tabSize": 2, "insertSpaces": true });
+        let edits = request(
+            "textDocument/formatting",
+            json!({ "textDocument": document, "options": options }),
+        );
+        assert_eq!(
+            apply_edits(source, &edits),
+            "class A {\n  Integer a = 1;\n  void m() {\n    Integer b = 2;\n  }\n}\n"
+        );
+
+        // only the statement ending on the typed line is formatted
+        let edits = request(
+            "textDocument/onTypeFormatting",
+            json!({
+                "textDocument": document, "position": { "line": 3, "character": 13 },
+                "ch": ";", "options": options
+            }),
+        );
+        assert_eq!(
+            apply_edits(source, &edits),
+            "class A {\n      Integer a=1;\nvoid m() {\n    Integer b = 2;\n  }\n}\n"
+        );
+
+        notify(
+            "textDocument/didChange",
+            json!({
+                "textDocument": { "uri": uri, "version": 2 },
+                "contentChanges": [{ "text": "class A {\n  Integer a = ;\n}\n" }]
+            }),
+        );
+        let errors = diagnostics();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0]["range"]["start"]["line"], 1);
+        assert_eq!(
+            request(
+                "textDocument/formatting",
+                json!({ "textDocument": document, "options": options }),
+            ),
+            Value::Null
+        );
+
+        // a broken line elsewhere does not stop range and on-type formatting
+        let broken = "class A {\n  Integer a = ;\n  void m() {\n      Integer b=2;\n  }\n\n}\n";
+        notify(
+            "textDocument/didChange",
+            json!({
+                "textDocument": { "uri": uri, "version": 3 },
+                "contentChanges": [{ "text": broken }]
+            }),
+        );
+        assert_eq!(diagnostics().len(), 1);
+        let fixed = broken.replace("      Integer b=2;", "    Integer b = 2;");
+        let edits = request(
+            "textDocument/onTypeFormatting",
+            json!({
+                "textDocument": document, "position": { "line": 3, "character": 18 },
+                "ch": ";", "options": options
+            }),
+        );
+        assert_eq!(apply_edits(broken, &edits), fixed);
+        let range = |line: u32| json!({ "start": { "line": line, "character": 0 }, "end": { "line": line + 1, "character": 0 } });
+        let edits = request(
+            "textDocument/rangeFormatting",
+            json!({ "textDocument": document, "range": range(3), "options": options }),
+        );
+        assert_eq!(apply_edits(broken, &edits), fixed);
+
+        // a blank line between members leaves the class alone
+        let edits = request(
+            "textDocument/rangeFormatting",
+            json!({ "textDocument": document, "range": range(5), "options": options }),
+        );
+        assert_eq!(apply_edits(broken, &edits), broken);
+
+        request("shutdown", Value::Null);
+        notify("exit", Value::Null);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn text_edits_only_touch_changes() {
+        let original = "class A {\n      Integer a=1;\n  String s = 'é';   Integer b;\n}\n";
+        let formatted = Formatter::format_one(original, Config::default()).unwrap();
+
+        let edits = text_edits(original, &formatted);
+        let edits = serde_json::to_value(&edits).unwrap();
+        assert_eq!(apply_edits(original, &edits), formatted);
+        assert_eq!(
+            edits[0],
+            serde_json::json!({
+                "range": {
+                    "start": { "line": 1, "character": 2 },
+                    "end": { "line": 1, "character": 6 }
+                },
+                "newText": ""
+            })
+        );
+    }
+
+    // Apply LSP text edits in JSON form, positions in UTF-16 code units.
+    fn apply_edits(text: &str, edits: &serde_json::Value) -> String {
+        let offset = |position: &serde_json::Value| {
+            let line = position["line"].as_u64().unwrap() as u32;
+            let character = position["character"].as_u64().unwrap() as u32;
+            sf_afmt::edits::offset(text, sf_afmt::edits::Position { line, character })
+        };
+        let mut result = text.to_string();
+        for edit in edits.as_array().unwrap().iter().rev() {
+            let range = offset(&edit["range"]["start"])..offset(&edit["range"]["end"]);
+            result.replace_range(range, edit["newText"].as_str().unwrap());
+        }
+        result
+    }
+
     fn run_scenario(dir_path: &str, scenario_name: &str) -> (u32, u32) {
         let mut total_tests = 0;
         let mut failed_tests = 0;
@@ -197,10 +1183,11 @@ mod tests {
         let formatter = Formatter::create_from_config(config_path, vec![file_path.clone()])
             .expect("Create formatter failed.");
 
-        let vec = formatter.format();
-        vec.into_iter()
+        let outcomes = formatter.format();
+        outcomes
+            .into_iter()
             .next()
-            .and_then(|result| result.ok())
+            .and_then(|outcome| outcome.formatted)
             .expect("format result failed.")
     }
 
@@ -0,0 +1,35 @@
+// Headless check of the `wasm` build, run from the repository root:
+// step 1: wasm-pack build --target nodejs --out-name afmt --no-default-features --features wasm
+// step 2: node tests/wasm/format.mjs
+
+import assert from 'assert';
+import { createRequire } from 'module';
+
+const require = createRequire(import.meta.url);
+const { format } = require('../../pkg/afmt.js');
+
+const source = 'public class A {\n        Integer   x=1;\n}\n';
+
+let result = format(source, '');
+assert.strictEqual(result.ok, true, result.error);
+assert.strictEqual(result.output, 'public class A {\n  Integer x = 1;\n}\n');
+
+result = format(source, '{"indent_size": 4}');
+assert.strictEqual(result.output, 'public class A {\n    Integer x = 1;\n}\n');
+
+result = format('public class A {', '');
+assert.strictEqual(result.ok, false);
+assert.match(result.error, /missing `}`/);
+
+result = format('class A {\n  List<Event> e = [SELECT TYPEOF What WHEN Account THEN Phone END FROM Event];\n}\n', '');
+assert.strictEqual(result.ok, false);
+assert.match(result.error, /unsupported node `type_of_clause`/);
+
+result = format(source, '');
+assert.strictEqual(result.ok, true, result.error);
+
+result = format(source, '{"max_width": "wide"}');
+assert.strictEqual(result.ok, false);
+assert.match(result.error, /Failed to parse config/);
+
+console.log('wasm format: ok');